@@ -1,4 +1,5 @@
 use std::ffi::c_void;
+use std::sync::Arc;
 
 use jni::{JNIEnv, objects::JClass, sys::{jobject, jlong, jint, jfloat}};
 use jni_fn::jni_fn;
@@ -11,7 +12,9 @@ use wgpu::{RequestAdapterOptions, DeviceDescriptor, Features, Limits, SurfaceCon
 use crate::{app::{App, ShaderType}, input_event::InputEvent};
 use crate::imgui_example::ImGUIExample;
 use crate::assets_helper::android_resources::AndroidResourceManager;
-use crate::input_event::EventType;
+use crate::input_event::{EventType, Key, Modifiers};
+use crate::input_state::{ActionMap, InputState};
+use crate::render_target::RenderTarget;
 
 #[no_mangle]
 #[jni_fn("com.crest.ukraine.JNITie")]
@@ -55,13 +58,14 @@ pub fn createNativeScene(env: *mut JNIEnv, _class: JClass, surface: jobject, ass
     surface.configure(&device, &surface_config); 
     
     let asset_manager = unsafe { ndk_sys::AAssetManager_fromJava(env as *mut _, asset_manager) };
-    let native_scene = ImGUIExample::new(&surface_config, &device, queue, ShaderType::WGSL, &AndroidResourceManager{ asset_manager });
+    let native_scene = ImGUIExample::new(&surface_config, &device, Arc::new(queue), ShaderType::WGSL, &AndroidResourceManager{ asset_manager });
 
     Box::into_raw(Box::new(GraphicApp{
         device,
         surface,
+        surface_config,
         native_scene,
-        prev_input_event: InputEvent::default(),
+        input_state: InputState::new(ActionMap::default()),
         //asset_manager
     })) as jlong
 }
@@ -70,25 +74,56 @@ pub fn createNativeScene(env: *mut JNIEnv, _class: JClass, surface: jobject, ass
 #[jni_fn("com.crest.ukraine.JNITie")]
 pub fn drawFrame(_env: *mut JNIEnv, _class: JClass, native_app: jlong){
     let graphic_app = unsafe { &mut *(native_app as *mut GraphicApp) };
-    <ImGUIExample as App<AndroidResourceManager>>::render(&mut graphic_app.native_scene, &graphic_app.surface, &graphic_app.device);
+    match RenderTarget::from_surface(&graphic_app.surface, &graphic_app.surface_config) {
+        Ok(target) => {
+            <ImGUIExample as App<AndroidResourceManager>>::process_input_state(&mut graphic_app.native_scene, &graphic_app.input_state);
+            graphic_app.input_state.end_frame();
+            <ImGUIExample as App<AndroidResourceManager>>::render(&mut graphic_app.native_scene, &target, &graphic_app.device, 1.0);
+            target.present();
+        },
+        Err(error) => log::warn!("Failed to acquire surface texture - {}", error),
+    }
 }
 
 #[jni_fn("com.crest.ukraine.JNITie")]
 pub fn handleEvent(_env: *mut JNIEnv, _class: JClass, native_app: jlong, action: jint, x: jfloat, y: jfloat){
-    let event = InputEvent::new(action, x, y);
     let graphic_app = unsafe { &mut *(native_app as *mut GraphicApp) };
-    let input_event = match graphic_app.prev_input_event.event_type {
-        EventType::Start | EventType::Move => {
-            Some(InputEvent::diff(&graphic_app.prev_input_event, &event))
-        },
-        _ => None
+    graphic_app.dispatch(InputEvent::new(action, x, y));
+}
+
+// MotionEvent with the pointer id of the finger that changed, the coords stay absolute
+#[jni_fn("com.crest.ukraine.JNITie")]
+pub fn handlePointerEvent(_env: *mut JNIEnv, _class: JClass, native_app: jlong, action: jint, pointer_id: jint, x: jfloat, y: jfloat){
+    let graphic_app = unsafe { &mut *(native_app as *mut GraphicApp) };
+    graphic_app.dispatch(InputEvent::from_motion(action, pointer_id as u64, x, y));
+}
+
+// KeyEvent action (0 down, 1 up), key code, meta state and the unicode char it produces if any
+#[jni_fn("com.crest.ukraine.JNITie")]
+pub fn handleKeyEvent(_env: *mut JNIEnv, _class: JClass, native_app: jlong, action: jint, key_code: jint, meta_state: jint, unicode_char: jint){
+    let graphic_app = unsafe { &mut *(native_app as *mut GraphicApp) };
+    let key = Key::from_android(key_code);
+    let event_type = match action {
+        0 => EventType::KeyDown(key),
+        1 => EventType::KeyUp(key),
+        _ => return
     };
-    graphic_app.prev_input_event = event;
-    if let Some(input_event) = input_event {
-        <ImGUIExample as App<AndroidResourceManager>>::process_input(&mut graphic_app.native_scene, &input_event);
+    let modifiers = Modifiers::from_android(meta_state);
+    graphic_app.dispatch(InputEvent { modifiers, ..InputEvent::with_type(event_type, [0.0; 2]) });
+
+    let c = char::from_u32(unicode_char as u32).filter(|c| !c.is_control());
+    if let (0, Some(c)) = (action, c) {
+        graphic_app.dispatch(InputEvent { modifiers, ..InputEvent::with_type(EventType::Char(c), [0.0; 2]) });
     }
 }
 
+// MotionEvent AXIS_HSCROLL/AXIS_VSCROLL of mice and touchpads
+#[jni_fn("com.crest.ukraine.JNITie")]
+pub fn handleScrollEvent(_env: *mut JNIEnv, _class: JClass, native_app: jlong, dx: jfloat, dy: jfloat){
+    let graphic_app = unsafe { &mut *(native_app as *mut GraphicApp) };
+    graphic_app.dispatch(InputEvent::with_type(EventType::Wheel, [dx, dy]));
+}
+
 struct NativeWindow {
     native_window: *mut ndk_sys::ANativeWindow,
     size: [u32; 2]
@@ -123,7 +158,14 @@ unsafe impl HasRawDisplayHandle for NativeWindow {
 pub struct GraphicApp {
     device: Device,
     surface: Surface,
-    prev_input_event: InputEvent,
+    surface_config: SurfaceConfiguration,
+    input_state: InputState,
     native_scene: ImGUIExample,
     //asset_manager: *mut ndk_sys::AAssetManager
-}
\ No newline at end of file
+}
+impl GraphicApp {
+    fn dispatch(&mut self, event: InputEvent) {
+        self.input_state.handle(&event);
+        <ImGUIExample as App<AndroidResourceManager>>::process_input(&mut self.native_scene, &event);
+    }
+}
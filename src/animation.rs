@@ -0,0 +1,175 @@
+use gltf::animation::{Interpolation, Property};
+
+use crate::model::Node;
+
+// One animated property of one node
+struct Channel {
+    // index into the model's nodes
+    node:           u32,
+    property:       Property,
+    interpolation:  Interpolation,
+    times:          Vec<f32>,
+    // floats per value, 3 for vectors, 4 for xyzw quaternions and one per morph target for weights
+    width:          usize,
+    // cubic splines store an in tangent, the value and an out tangent per keyframe
+    values:         Vec<f32>,
+}
+
+impl Channel {
+    fn element(&self, index: usize) -> &[f32] {
+        &self.values[index * self.width..(index + 1) * self.width]
+    }
+
+    fn value(&self, keyframe: usize) -> &[f32] {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.element(keyframe * 3 + 1),
+            _ => self.element(keyframe),
+        }
+    }
+
+    fn sample(&self, time: f32) -> Vec<f32> {
+        let next = self.times.partition_point(|keyframe_time| *keyframe_time <= time);
+        // before the first and after the last keyframe the ends are held
+        if next == 0 {
+            return self.value(0).to_vec();
+        }
+        if next == self.times.len() {
+            return self.value(next - 1).to_vec();
+        }
+        let prev = next - 1;
+        let dt = self.times[next] - self.times[prev];
+        let t = (time - self.times[prev]) / dt;
+        let is_rotation = self.property == Property::Rotation;
+
+        match self.interpolation {
+            Interpolation::Step => self.value(prev).to_vec(),
+            Interpolation::Linear if is_rotation => {
+                let (from, mut to) = (quat(self.value(prev)), quat(self.value(next)));
+                // q and -q are the same rotation, the closer one takes the short way around
+                if glm::quat_dot(&from, &to) < 0.0 {
+                    to = -to;
+                }
+                glm::quat_slerp(&from, &to, t).coords.as_slice().to_vec()
+            },
+            Interpolation::Linear => self.value(prev).iter().zip(self.value(next)).map(|(from, to)| from + (to - from) * t).collect(),
+            Interpolation::CubicSpline => {
+                let (t2, t3) = (t * t, t * t * t);
+                let out_tangents = self.element(prev * 3 + 2);
+                let in_tangents = self.element(next * 3);
+                let value: Vec<f32> = (0..self.width).map(|i| {
+                    self.value(prev)[i] * (2.0 * t3 - 3.0 * t2 + 1.0) + out_tangents[i] * dt * (t3 - 2.0 * t2 + t)
+                        + self.value(next)[i] * (-2.0 * t3 + 3.0 * t2) + in_tangents[i] * dt * (t3 - t2)
+                }).collect();
+                if is_rotation { glm::quat_normalize(&quat(&value)).coords.as_slice().to_vec() } else { value }
+            },
+        }
+    }
+}
+
+fn quat(value: &[f32]) -> glm::Quat {
+    glm::quat(value[0], value[1], value[2], value[3])
+}
+
+pub struct Animation {
+    pub name:       Option<String>,
+    // time of the last keyframe of all channels
+    pub duration:   f32,
+    channels:       Vec<Channel>,
+}
+
+impl Animation {
+    // node_index maps the document's node indices to the model's, channels of nodes outside the loaded scenes are dropped
+    pub fn new(animation: &gltf::Animation, buffer_data: &[Vec<u8>], node_index: impl Fn(usize) -> Option<u32>) -> Self {
+        let channels: Vec<Channel> = animation.channels().filter_map(|channel| {
+            let node = node_index(channel.target().node().index())?;
+            let reader = channel.reader(|buffer| buffer_data.get(buffer.index()).map(Vec::as_slice));
+            let times: Vec<f32> = reader.read_inputs()?.collect();
+            let (width, values): (usize, Vec<f32>) = match reader.read_outputs()? {
+                gltf::animation::util::ReadOutputs::Translations(iter) | gltf::animation::util::ReadOutputs::Scales(iter) => (3, iter.flatten().collect()),
+                gltf::animation::util::ReadOutputs::Rotations(iter) => (4, iter.into_f32().flatten().collect()),
+                gltf::animation::util::ReadOutputs::MorphTargetWeights(iter) => {
+                    let target_count = channel.target().node().mesh()?.primitives().next()?.morph_targets().len();
+                    (target_count, iter.into_f32().collect())
+                },
+            };
+            let interpolation = channel.sampler().interpolation();
+            let values_per_keyframe = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+            if times.is_empty() || width == 0 || values.len() < times.len() * values_per_keyframe * width {
+                println!("Channel {} of animation {} doesn't have a value for every keyframe", channel.index(), animation.index());
+                return None;
+            }
+            Some(Channel { node, property: channel.target().property(), interpolation, times, width, values })
+        }).collect();
+        let duration = channels.iter().filter_map(|channel| channel.times.last()).fold(0.0, |duration: f32, time| duration.max(*time));
+        Self { name: animation.name().map(str::to_string), duration, channels }
+    }
+
+    // Poses the nodes as they are at time
+    pub fn apply(&self, time: f32, nodes: &mut [Node]) {
+        for channel in &self.channels {
+            let value = channel.sample(time);
+            let node = &mut nodes[channel.node as usize];
+            match channel.property {
+                Property::Translation => node.transform.translation = glm::make_vec3(&value),
+                Property::Rotation => node.transform.rotation = quat(&value),
+                Property::Scale => node.transform.scale = glm::make_vec3(&value),
+                // the weights past the ones the node has room for are dropped
+                Property::MorphTargetWeights => node.morph_weights.iter_mut().zip(value).for_each(|(weight, value)| *weight = value),
+            }
+        }
+    }
+}
+
+// A node's local transform, what animations change
+#[derive(Debug, Copy, Clone)]
+pub struct NodeTransform {
+    pub translation:    glm::Vec3,
+    pub rotation:       glm::Quat,
+    pub scale:          glm::Vec3,
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        Self { translation: glm::Vec3::zeros(), rotation: glm::Quat::identity(), scale: glm::vec3(1.0, 1.0, 1.0) }
+    }
+}
+
+impl NodeTransform {
+    pub fn matrix(&self) -> glm::Mat4 {
+        glm::translation(&self.translation) * glm::quat_to_mat4(&self.rotation) * glm::scaling(&self.scale)
+    }
+}
+
+// Which animation of a model plays and where it's at
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Playback {
+    pub animation:  usize,
+    pub time:       f32,
+    pub playing:    bool,
+    // starts over after the end instead of stopping there
+    pub looping:    bool,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self { animation: 0, time: 0.0, playing: true, looping: true }
+    }
+}
+
+impl Playback {
+    // Moves time forward by delta, wrapping or stopping at the end of an animation lasting duration
+    pub fn advance(&mut self, delta: f32, duration: f32) {
+        if !self.playing {
+            return;
+        }
+        self.time += delta;
+        if self.time > duration {
+            if self.looping && duration > 0.0 {
+                self.time = self.time.rem_euclid(duration);
+            } else {
+                self.time = duration;
+                self.playing = false;
+            }
+        }
+    }
+}
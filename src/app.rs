@@ -1,43 +1,123 @@
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
 use structopt::StructOpt;
 use wgpu::{Features, Queue};
 
 use crate::input_event::InputEvent;
+use crate::input_state::InputState;
 use crate::assets_helper::ResourceManager;
+use crate::camera::Camera;
+use crate::render_target::RenderTarget;
 
 pub trait App<T: ResourceManager> {
-    fn get_extra_device_features(app_variant: AppVariant) -> Features {
+    fn get_extra_device_features(app_variant: &AppVariant) -> Features where Self: Sized {
         match app_variant.shader_type {
             ShaderType::WGSL => Features::empty(),
             ShaderType::SPIRV => Features::SPIRV_SHADER_PASSTHROUGH,
         }
     }
+    // Requested on top of the extra ones whenever the adapter supports them
+    fn get_optional_device_features(_app_variant: &AppVariant) -> Features where Self: Sized {
+        Features::empty()
+    }
     fn new(
         sc: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
-        queue: Queue,
+        queue: Arc<Queue>,
         shader_type: ShaderType,
         resource_manager: &T
-    ) -> Self;
+    ) -> Self where Self: Sized;
+
+    // What the runners call, apps that need more than the shader type from the command line override it
+    fn from_variant(
+        sc: &wgpu::SurfaceConfiguration,
+        device: &wgpu::Device,
+        queue: Arc<Queue>,
+        app_variant: &AppVariant,
+        resource_manager: &T
+    ) -> Self where Self: Sized {
+        Self::new(sc, device, queue, app_variant.shader_type, resource_manager)
+    }
 
+    // Raw events as they arrive, returning true swallows them before the runner's own hotkeys
     fn process_input(&mut self, _event: &InputEvent) -> bool {
         false
     }
+    // Once per frame before tick, with what the frame's events added up to
+    fn process_input_state(&mut self, _input: &InputState) {}
+    // The camera the runner records and plays camera paths with, if the app has one
+    fn camera(&mut self) -> Option<&mut dyn Camera> {
+        None
+    }
 
     fn resize(&mut self, _sc: &wgpu::SurfaceConfiguration, _device: &wgpu::Device) {}
+    // Called once per frame with the frame delta, for things that don't need to be deterministic (cameras, UI)
     fn tick(&mut self, _delta: f32) {}
+    // Simulation step. With --fixed_timestep it's called zero or more times per frame with a constant step,
+    // otherwise once per frame with the frame delta
+    fn update(&mut self, _step: f32) {}
+    // alpha is how far the frame lies between the previous and the latest simulation step, in [0, 1].
+    // It's always 1 without a fixed timestep.
     fn render(
         &mut self,
-        frame: &wgpu::Surface,
+        target: &RenderTarget,
         device: &wgpu::Device,
-    ) -> Result<(), wgpu::SurfaceError>;
+        alpha: f32,
+    );
 }
 
-#[derive(StructOpt, Debug, Clone, Copy)]
+#[derive(StructOpt, Debug, Clone)]
 #[structopt(name = "settings")]
 pub struct AppVariant {
     #[structopt(short = "s", long = "shader_type", default_value = "WGSL")]
     pub(crate) shader_type: ShaderType,
+    #[structopt(short = "e", long = "example", default_value = "pbr")]
+    pub(crate) example: Example,
+
+    // Renders offscreen without creating a window and dumps the frames as png files
+    #[structopt(long = "headless")]
+    pub(crate) headless: bool,
+    #[structopt(long = "frames", default_value = "1")]
+    pub(crate) frames: u32,
+    #[structopt(long = "frame_delta", default_value = "0.016")]
+    pub(crate) frame_delta: f32,
+    #[structopt(long = "width", default_value = "1280")]
+    pub(crate) width: u32,
+    #[structopt(long = "height", default_value = "720")]
+    pub(crate) height: u32,
+    // Where headless frames, screenshots (F12) and recorded sequences (F11) are written to
+    #[structopt(long = "output_dir", default_value = "./output", parse(from_os_str))]
+    pub(crate) output_dir: PathBuf,
+    // Simulation rate of recorded sequences, frames are spaced 1/capture_fps apart regardless of the real frame time
+    #[structopt(long = "capture_fps", default_value = "60")]
+    pub(crate) capture_fps: f32,
+    // Simulation rate in Hz, App::update gets called with a constant 1/fixed_timestep step
+    #[structopt(long = "fixed_timestep")]
+    pub(crate) fixed_timestep: Option<f32>,
+    // Dumps CPU frame times and GPU scope timings of the windowed run as frame,scope,ms rows
+    #[structopt(long = "profile_csv", parse(from_os_str))]
+    pub(crate) profile_csv: Option<PathBuf>,
+    // Forces a software (fallback) adapter, useful on CI machines without a GPU
+    #[structopt(long = "software")]
+    pub(crate) software: bool,
+    // Json file rebinding actions, the ones it leaves out keep the defaults of ActionMap. See
+    // assets/input_bindings.json, which moves the fly camera with ZQSD on AZERTY keyboards
+    #[structopt(long = "bindings", parse(from_os_str))]
+    pub(crate) bindings: Option<PathBuf>,
+    // Json or .ron camera path the camera follows instead of the input, see camera_path.rs
+    #[structopt(long = "camera_path", parse(from_os_str))]
+    pub(crate) camera_path: Option<PathBuf>,
+    // Where the keyframes recorded with K are saved to, as RON when it ends with .ron
+    #[structopt(long = "record_camera_path", parse(from_os_str))]
+    pub(crate) record_camera_path: Option<PathBuf>,
+}
+
+impl Default for AppVariant {
+    // Same as running without any command line arguments
+    fn default() -> Self {
+        AppVariant::from_iter(std::iter::empty::<String>())
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -66,3 +146,67 @@ impl std::fmt::Display for ShaderType {
         }
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Example {
+    Boxes,
+    Flipboard,
+    FullscreenTriangle,
+    GLTFViewer,
+    ImGUI,
+    PBR,
+    ShadowMapping,
+    Skybox,
+}
+
+impl Example {
+    pub const ALL: [Example; 8] = [
+        Example::Boxes,
+        Example::Flipboard,
+        Example::FullscreenTriangle,
+        Example::GLTFViewer,
+        Example::ImGUI,
+        Example::PBR,
+        Example::ShadowMapping,
+        Example::Skybox,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Example::Boxes => "boxes",
+            Example::Flipboard => "flipboard",
+            Example::FullscreenTriangle => "fullscreen_triangle",
+            Example::GLTFViewer => "gltf_viewer",
+            Example::ImGUI => "imgui",
+            Example::PBR => "pbr",
+            Example::ShadowMapping => "shadow_mapping",
+            Example::Skybox => "skybox",
+        }
+    }
+
+    pub fn next(&self) -> Example {
+        let index = Example::ALL.iter().position(|example| example == self).unwrap();
+        Example::ALL[(index + 1) % Example::ALL.len()]
+    }
+
+    pub fn prev(&self) -> Example {
+        let index = Example::ALL.iter().position(|example| example == self).unwrap();
+        Example::ALL[(index + Example::ALL.len() - 1) % Example::ALL.len()]
+    }
+}
+
+impl FromStr for Example {
+    type Err = ParseError;
+    fn from_str(input: &str) -> Result<Example, Self::Err> {
+        Example::ALL.iter()
+            .find(|example| example.name().eq_ignore_ascii_case(input))
+            .copied()
+            .ok_or("Could not parse an Example")
+    }
+}
+
+impl std::fmt::Display for Example {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -28,10 +28,12 @@ pub trait ResourceManager {
     fn load_binary(&self, file_name: &str) -> anyhow::Result<Vec<u8>>;
     fn load_base64(&self, data: &str) -> anyhow::Result<Vec<u8>> {
         //let sss: String = data.replace("data:image/jpeg;base64,", "").trim().into();
-        let sss = data.split("base64,").nth(1).unwrap().trim();
-        match base64::decode(sss) {
+        let Some(sss) = data.split("base64,").nth(1) else {
+            bail!("Not a base64 data URI");
+        };
+        match base64::decode(sss.trim()) {
             Ok(data) => Ok(data),
-            Err(error) => bail!("Failed to decode Base64 data"),
+            Err(error) => bail!("Failed to decode Base64 data - {}", error),
         }
     }
     fn load_obj_model(&self, file_name: &str, device: &wgpu::Device) -> anyhow::Result<Vec<Mesh>>;
@@ -156,7 +158,7 @@ impl ResourceManager for DesktopResourceManager {
 
 #[cfg(target_os = "android")]
 pub mod android_resources {
-    use anyhow::Ok;
+    use anyhow::{bail, Ok};
     use ndk_sys::AAssetManager;
     use super::ResourceManager;
 
@@ -169,11 +171,14 @@ pub mod android_resources {
             unsafe {
                 let filename_cstr = std::ffi::CString::new(file_name).unwrap();
                 let asset = ndk_sys::AAssetManager_open(self.asset_manager, filename_cstr.as_ptr(), ndk_sys::AASSET_MODE_UNKNOWN as i32);
+                if asset.is_null() {
+                    bail!("Asset {} not found", file_name);
+                }
                 let buffer = ndk_sys::AAsset_getBuffer(asset);
                 let length = ndk_sys::AAsset_getLength(asset);
                 let data = std::slice::from_raw_parts(buffer as *const u8, length as usize).to_vec();
                 ndk_sys::AAsset_close(asset);
-                Ok(String::from_utf8(data).unwrap())
+                Ok(String::from_utf8(data)?)
             }
         }
 
@@ -181,6 +186,9 @@ pub mod android_resources {
             unsafe {
                 let filename_cstr = std::ffi::CString::new(file_name).unwrap();
                 let asset = ndk_sys::AAssetManager_open(self.asset_manager, filename_cstr.as_ptr(), ndk_sys::AASSET_MODE_UNKNOWN as i32);
+                if asset.is_null() {
+                    bail!("Asset {} not found", file_name);
+                }
                 let buffer = ndk_sys::AAsset_getBuffer(asset);
                 let length = ndk_sys::AAsset_getLength(asset);
                 let data = std::slice::from_raw_parts(buffer as *const u8, length as usize).to_vec();
@@ -1,55 +1,259 @@
-use std::{f32::consts::PI, mem};
+use std::f32::consts::PI;
 
-use wgpu::{Buffer, BindGroup, Device, util::DeviceExt, Queue, BindGroupLayoutEntry, BindGroupEntry, BufferUsages, BufferDescriptor};
-use crate::input_event::{InputEvent, EventType};
+use bytemuck::Zeroable;
+
+use wgpu::{Buffer, BindGroup, Device, util::DeviceExt, Queue, BindGroupLayoutEntry, BindGroupEntry, BindGroupLayoutDescriptor, ShaderStages, CompareFunction};
+use crate::{input_state::InputState, model::Aabb};
+
+// What every camera writes to its uniform buffer. The first four members are what the precompiled
+// pbr shaders expect, WGSL shaders declare the same struct:
+//
+// struct CameraUniform {
+//     proj:       mat4x4<f32>,
+//     model:      mat4x4<f32>,
+//     view:       mat4x4<f32>,
+//     position:   vec4<f32>,
+//     view_proj:  mat4x4<f32>,
+//     inv_proj:   mat4x4<f32>,
+//     inv_view:   mat4x4<f32>,
+//     viewport:   vec4<f32>,
+//     near:       f32,
+//     far:        f32,
+//     jitter:     vec2<f32>,
+// };
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub proj:       [[f32; 4]; 4],
+    // always identity, kept for the shaders that still multiply by it
+    pub model:      [[f32; 4]; 4],
+    pub view:       [[f32; 4]; 4],
+    pub position:   [f32; 4],
+    pub view_proj:  [[f32; 4]; 4],
+    pub inv_proj:   [[f32; 4]; 4],
+    pub inv_view:   [[f32; 4]; 4],
+    // x, y, width, height in pixels
+    pub viewport:   [f32; 4],
+    pub near:       f32,
+    // infinity for the reverse-Z projection
+    pub far:        f32,
+    // subpixel offset already baked into proj, in pixels
+    pub jitter:     [f32; 2],
+}
+
+// Projection parameters the cameras share
+#[derive(Debug, Copy, Clone)]
+pub struct Lens {
+    pub projection: Projection,
+    pub fov: f32,
+    pub width: f32,
+    pub height: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    // subpixel offset for temporal accumulation, in pixels
+    pub jitter: [f32; 2],
+}
+
+impl Lens {
+    pub fn new(width: f32, height: f32, fov: f32, znear: f32, zfar: f32) -> Self {
+        Self { projection: Projection::Perspective, fov, width, height, znear, zfar, jitter: [0.0; 2] }
+    }
+
+    pub fn proj(&self) -> glm::Mat4 {
+        // shifts clip space xy by the jitter scaled by w, so it's the same amount of pixels at any depth
+        let jitter_matrix = glm::translation(&glm::vec3(2.0 * self.jitter[0] / self.width, -2.0 * self.jitter[1] / self.height, 0.0));
+        jitter_matrix * self.projection.matrix(self.fov, self.width, self.height, self.znear, self.zfar)
+    }
+
+    // Tangent of half the narrower fov
+    fn tan_half_fov(&self) -> f32 {
+        (self.fov * 0.5).tan().abs() * (self.width / self.height).min(1.0)
+    }
+
+    // World units a pixel covers at the given distance
+    fn units_per_pixel(&self, distance: f32) -> f32 {
+        match self.projection {
+            Projection::Orthographic { height } => height / self.height,
+            _ => 2.0 * distance * (self.fov * 0.5).tan().abs() / self.height,
+        }
+    }
+
+    pub fn frustum(&self, view: &glm::Mat4) -> Frustum {
+        // only the perspective projection maps depth to -1..1
+        Frustum::from_matrix(&(self.proj() * view), self.projection != Projection::Perspective)
+    }
+
+    pub fn uniform(&self, view: &glm::Mat4, eye: &glm::Vec3) -> CameraUniform {
+        let proj = self.proj();
+        CameraUniform {
+            proj: proj.into(),
+            model: glm::Mat4::identity().into(),
+            view: (*view).into(),
+            position: [eye.x, eye.y, eye.z, 1.0],
+            view_proj: (proj * view).into(),
+            inv_proj: glm::inverse(&proj).into(),
+            inv_view: glm::inverse(view).into(),
+            viewport: [0.0, 0.0, self.width, self.height],
+            near: self.znear,
+            far: if self.projection == Projection::ReverseZInfinite { f32::INFINITY } else { self.zfar },
+            jitter: self.jitter,
+        }
+    }
+}
+
+pub const CAMERA_BGL: BindGroupLayoutDescriptor = BindGroupLayoutDescriptor{
+    label: Some("camera_bind_group_layout"),
+    entries: &[BindGroupLayoutEntry{
+        binding: 0,
+        visibility: ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None
+        },
+        count: None,
+    }],
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // Depth is 1 at the near plane and reaches 0 at infinity, zfar is ignored.
+    // Pipelines need depth_compare() and the depth buffer has to be cleared to depth_clear().
+    ReverseZInfinite,
+    // Height of the view volume in world units, the width follows the aspect ratio
+    Orthographic { height: f32 },
+}
+
+impl Projection {
+    pub fn depth_compare(&self) -> CompareFunction {
+        match self {
+            Projection::ReverseZInfinite => CompareFunction::GreaterEqual,
+            _ => CompareFunction::LessEqual,
+        }
+    }
+
+    // The depth of the farthest point
+    pub fn depth_clear(&self) -> f32 {
+        match self {
+            Projection::ReverseZInfinite => 0.0,
+            _ => 1.0,
+        }
+    }
+
+    pub fn matrix(&self, fov: f32, width: f32, height: f32, znear: f32, zfar: f32) -> glm::Mat4 {
+        match self {
+            Projection::Perspective => glm::perspective_fov(fov, width, height, znear, zfar),
+            Projection::ReverseZInfinite => {
+                let f = 1.0 / (fov * 0.5).tan();
+                glm::mat4(
+                    f * height / width, 0.0, 0.0,  0.0,
+                    0.0,                f,   0.0,  0.0,
+                    0.0,                0.0, 0.0,  znear,
+                    0.0,                0.0, -1.0, 0.0,
+                )
+            },
+            Projection::Orthographic { height: view_height } => {
+                let half_height = view_height * 0.5;
+                let half_width = half_height * width / height;
+                glm::ortho_rh_zo(-half_width, half_width, -half_height, half_height, znear, zfar)
+            },
+        }
+    }
+}
+
+// Where a camera is and where it looks, cameras look down -z of the orientation without rolling
+#[derive(Debug, Copy, Clone)]
+pub struct Pose {
+    pub position: glm::Vec3,
+    pub orientation: glm::Quat,
+}
+
+impl Pose {
+    // Yaw turns around +y starting from -z, pitch tilts up
+    pub fn from_yaw_pitch(position: glm::Vec3, yaw: f32, pitch: f32) -> Self {
+        let orientation = glm::quat_angle_axis(yaw, &glm::vec3(0., 1., 0.)) * glm::quat_angle_axis(pitch, &glm::vec3(1., 0., 0.));
+        Self { position, orientation }
+    }
+
+    pub fn forward(&self) -> glm::Vec3 {
+        glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0., 0., -1.))
+    }
+
+    // Inverse of from_yaw_pitch, any roll is dropped
+    pub fn yaw_pitch(&self) -> (f32, f32) {
+        let forward = self.forward();
+        ((-forward.x).atan2(-forward.z), forward.y.clamp(-1.0, 1.0).asin())
+    }
+}
+
+// Distance change per wheel line
+const ZOOM_STEP: f32 = 0.9;
 
 pub trait Camera {
-    fn input(&mut self, event: &InputEvent);
+    // Called once per frame with the accumulated input, before tick
+    fn input(&mut self, input: &InputState);
     fn tick(&mut self, time_delta: f32, queue: &Queue);
+    // Moves the camera so that the box fills the view
+    fn frame_bounds(&mut self, bounds: &Aabb);
+    fn pose(&self) -> Pose;
+    // Jumps to the pose, camera paths call it every frame
+    fn set_pose(&mut self, pose: &Pose);
+    // Of the view written by the latest tick
+    fn frustum(&self) -> Frustum;
+    // Pipelines and depth clears have to match it
+    fn projection(&self) -> Projection;
+    fn bind_group(&self) -> &BindGroup;
+    fn get_bind_group(&self, index: u32) -> (BindGroupLayoutEntry, BindGroupEntry<'_>);
 }
 
-pub struct ArcballCamera {
-    width: f32,
-    height: f32,
-    fov: f32,
-    znear: f32,
-    zfar: f32,
-    speed: f32,
+// The six clip planes of a view projection, for culling on the CPU
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    planes: [glm::Vec4; 6],
+}
 
-    camera_buffer: Buffer,
-    pub camera_bind_group: BindGroup,
+impl Frustum {
+    // Planes are w ± x, w ± y and w - z of clip space, plus w + z or just z depending on whether depth
+    // goes from -1 or 0 to 1
+    pub fn from_matrix(view_proj: &glm::Mat4, zero_to_one_depth: bool) -> Self {
+        let row = |index: usize| view_proj.row(index).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let near = if zero_to_one_depth { z } else { w + z };
+        Self { planes: [w + x, w - x, w + y, w - y, near, w - z] }
+    }
 
-    dist: f32,
-    pub azimuth: f32,
-    pub polar: f32,
+    // False only if the box is entirely behind one of the planes
+    pub fn intersects(&self, bounds: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane's normal
+            let corner = glm::vec3(
+                if plane.x >= 0.0 { bounds.max.x } else { bounds.min.x },
+                if plane.y >= 0.0 { bounds.max.y } else { bounds.min.y },
+                if plane.z >= 0.0 { bounds.max.z } else { bounds.min.z },
+            );
+            glm::dot(&plane.xyz(), &corner) + plane.w >= 0.0
+        })
+    }
+}
 
-    prev_input_event: InputEvent,
+// The uniform buffer and bind group every camera writes its matrices to, so the examples' pipelines
+// don't care which camera drives them
+pub struct CameraBinding {
+    camera_buffer: Buffer,
+    pub camera_bind_group: BindGroup,
+    frustum: Frustum,
 }
 
-impl ArcballCamera {
-    pub fn new(device: &Device, width: f32, height: f32,
-                fov: f32, znear: f32, zfar: f32, speed: f32, dist: f32) -> Self {
-        let arr = [0.0f32; 64];
+impl CameraBinding {
+    pub fn new(device: &Device) -> Self {
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Buffer"),
-            contents: bytemuck::cast_slice(&[arr]),
+            contents: bytemuck::bytes_of(&CameraUniform::zeroed()),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
-        
-        let camera_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            label: Some("camera_bind_group_layout"),
-        });
+
+        let camera_bind_group_layout = device.create_bind_group_layout(&CAMERA_BGL);
         let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &camera_bind_group_layout,
             entries: &[wgpu::BindGroupEntry {
@@ -58,37 +262,16 @@ impl ArcballCamera {
             }],
             label: Some("camera_bind_group"),
         });
-        
-        Self{
-            width,
-            height,
-            fov,
-            znear,
-            zfar,
-            speed,
-
-            camera_buffer,
-            camera_bind_group,
 
-            dist,
-            azimuth: 0.,
-            polar: 0.,
-
-            prev_input_event: InputEvent::default(),
-        }
+        Self { camera_buffer, camera_bind_group, frustum: Frustum::from_matrix(&glm::Mat4::identity(), false) }
     }
 
-    pub fn get_bind_group(&self, index: u32) -> (BindGroupLayoutEntry, BindGroupEntry) {
+    pub fn get_bind_group(&self, index: u32) -> (BindGroupLayoutEntry, BindGroupEntry<'_>) {
         (
-            wgpu::BindGroupLayoutEntry {
+            // the same entry as in CAMERA_BGL, so shaders see the camera in the same stages whichever layout they use
+            BindGroupLayoutEntry {
                 binding: index,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
+                ..CAMERA_BGL.entries[0]
             },
             wgpu::BindGroupEntry {
                 binding: index,
@@ -96,49 +279,264 @@ impl ArcballCamera {
             }
         )
     }
+
+    pub fn write(&mut self, queue: &Queue, lens: &Lens, view: &glm::Mat4, eye: &glm::Vec3) {
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&lens.uniform(view, eye)));
+        self.frustum = lens.frustum(view);
+    }
+}
+
+// Distance from the center of the box at which its bounding sphere fits the narrower fov
+fn framing_distance(bounds: &Aabb, lens: &Lens) -> f32 {
+    let radius = glm::length(&(bounds.max - bounds.min)) * 0.5;
+    radius / lens.tan_half_fov().atan().sin()
+}
+
+pub struct ArcballCamera {
+    pub lens: Lens,
+    speed: f32,
+
+    binding: CameraBinding,
+
+    dist: f32,
+    min_dist: f32,
+    max_dist: f32,
+    pub azimuth: f32,
+    pub polar: f32,
+    pub target: glm::Vec3,
+}
+
+impl ArcballCamera {
+    pub fn new(device: &Device, width: f32, height: f32,
+                fov: f32, znear: f32, zfar: f32, speed: f32, dist: f32) -> Self {
+        Self{
+            lens: Lens::new(width, height, fov, znear, zfar),
+            speed,
+
+            binding: CameraBinding::new(device),
+
+            dist,
+            min_dist: znear * 2.0,
+            max_dist: zfar * 0.5,
+            azimuth: 0.,
+            polar: 0.,
+            target: glm::vec3(0., -0.125, 0.),
+        }
+    }
+
+    fn eye_offset(&self) -> glm::Vec3 {
+        let eye = glm::vec3::<f32>(0., 0., self.dist);
+        glm::rotate_y_vec3(&glm::rotate_x_vec3(&eye, self.polar), self.azimuth)
+    }
 }
 
 impl Camera for ArcballCamera {
-    fn input(&mut self, new_event: &InputEvent) {
-        let event = InputEvent::diff(&self.prev_input_event, &new_event);
-        match &event.event_type {
-            EventType::Move => {
-                self.azimuth -= event.coords[0] / self.width * self.speed;
-                self.polar   -= event.coords[1] / self.height * self.speed;
-                self.polar = self.polar.clamp(-PI * 0.35, PI * 0.35);
-            },
-            _ => (),
+    fn input(&mut self, input: &InputState) {
+        let orbit = input.value("orbit");
+        self.azimuth -= orbit[0] / self.lens.width * self.speed;
+        self.polar   -= orbit[1] / self.lens.height * self.speed;
+        self.polar = self.polar.clamp(-PI * 0.35, PI * 0.35);
+
+        let zoom = input.value("zoom")[1];
+        let dist = (self.dist * ZOOM_STEP.powf(zoom)).clamp(self.min_dist, self.max_dist);
+        // distance doesn't change the size of things in an orthographic view
+        if let Projection::Orthographic { height } = &mut self.lens.projection {
+            *height *= dist / self.dist;
+        }
+        self.dist = dist;
+
+        // drags the target along the view plane so that the point under the cursor follows it
+        let pan = input.value("pan");
+        if pan != [0.0; 2] {
+            let forward = -glm::normalize(&self.eye_offset());
+            let right = glm::normalize(&glm::cross(&forward, &glm::vec3(0., 1., 0.)));
+            let up = glm::cross(&right, &forward);
+            self.target += (-right * pan[0] + up * pan[1]) * self.lens.units_per_pixel(self.dist);
         }
-        self.prev_input_event = new_event.clone();
     }
 
     fn tick(&mut self, time_delta: f32, queue: &Queue) {
-        let mut eye = glm::vec3::<f32>(0., 0., self.dist);
-        eye = glm::rotate_x_vec3(&eye, self.polar);
-        eye = glm::rotate_y_vec3(&eye, self.azimuth);
+        let eye = self.target + self.eye_offset();
 
-        let proj = glm::perspective_fov(self.fov, self.width, self.height, self.znear, self.zfar);
-        let view = glm::look_at(&eye, &glm::vec3::<f32>(0., -0.125, 0.), &glm::vec3::<f32>(0., 1., 0.));
-        // let mat = glm::perspective_fov(self.fov, self.width, self.height, self.znear, self.zfar) * 
+        let view = glm::look_at(&eye, &self.target, &glm::vec3::<f32>(0., 1., 0.));
+        // let mat = glm::perspective_fov(self.fov, self.width, self.height, self.znear, self.zfar) *
         //                                               glm::look_at(&eye, &glm::vec3::<f32>(0., -0.125, 0.), &glm::vec3::<f32>(0., 1., 0.));
-        
+
         //let mat = glm::ortho(-2.0, 2.0, -1.0, 1.0, -1.0, 1.0)
         //                                            * glm::look_at(&eye, &glm::Vec3::zeros(), &glm::vec3::<f32>(0., 1., 0.));
-        
-        // struct CameraParams {
-        //     projection :                      mat4x4<f32>,
-        //     model :                           mat4x4<f32>,
-        //     view :                            mat4x4<f32>,
-        //     position :                        vec3<f32>,
-        //   };
-        
-        let mut uniform = Vec::<f32>::new();
-        uniform.extend(proj.iter());
-        uniform.extend(glm::Mat4::identity().iter());
-        uniform.extend(view.iter());
-        // uniform.extend(mat.iter());
-        uniform.extend(eye.iter());
-        uniform.push(1.0);
-        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&uniform));
-    }
-}
\ No newline at end of file
+        self.binding.write(queue, &self.lens, &view, &eye);
+    }
+
+    // Looks at the center of the box
+    fn frame_bounds(&mut self, bounds: &Aabb) {
+        let radius = glm::length(&(bounds.max - bounds.min)) * 0.5;
+        self.target = (bounds.min + bounds.max) * 0.5;
+        self.dist = framing_distance(bounds, &self.lens);
+        self.min_dist = self.min_dist.min(radius * 0.1);
+        self.max_dist = self.max_dist.max(self.dist * 2.0);
+        if let Projection::Orthographic { height } = &mut self.lens.projection {
+            *height = radius * 2.0 * (self.lens.height / self.lens.width).max(1.0);
+        }
+    }
+
+    fn pose(&self) -> Pose {
+        Pose::from_yaw_pitch(self.target + self.eye_offset(), self.azimuth, self.polar)
+    }
+
+    // Keeps the distance and moves the target in front of the new position
+    fn set_pose(&mut self, pose: &Pose) {
+        (self.azimuth, self.polar) = pose.yaw_pitch();
+        self.target = pose.position + pose.forward() * self.dist;
+    }
+
+    fn frustum(&self) -> Frustum {
+        self.binding.frustum
+    }
+
+    fn bind_group(&self) -> &BindGroup {
+        &self.binding.camera_bind_group
+    }
+
+    fn projection(&self) -> Projection {
+        self.lens.projection
+    }
+
+    fn get_bind_group(&self, index: u32) -> (BindGroupLayoutEntry, BindGroupEntry<'_>) {
+        self.binding.get_bind_group(index)
+    }
+}
+
+// How quickly the fly camera catches up with the input, higher is snappier
+const FLY_SMOOTHING: f32 = 12.0;
+const FLY_FAST_MULTIPLIER: f32 = 4.0;
+const FLY_SLOW_MULTIPLIER: f32 = 0.25;
+
+// First person camera. WASD (or the arrows) move along the view direction, Q/E down and up,
+// dragging looks around and shift/ctrl speed the movement up/down.
+pub struct FlyCamera {
+    pub lens: Lens,
+    // radians per pixel dragged
+    look_speed: f32,
+    // units per second
+    pub move_speed: f32,
+
+    binding: CameraBinding,
+
+    pub position: glm::Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    target_yaw: f32,
+    target_pitch: f32,
+    velocity: glm::Vec3,
+    // direction and speed multiplier the input asks for, in view space
+    wish_dir: glm::Vec3,
+    speed_multiplier: f32,
+}
+
+impl FlyCamera {
+    pub fn new(device: &Device, width: f32, height: f32,
+                fov: f32, znear: f32, zfar: f32, look_speed: f32, move_speed: f32, position: glm::Vec3) -> Self {
+        Self {
+            lens: Lens::new(width, height, fov, znear, zfar),
+            look_speed,
+            move_speed,
+
+            binding: CameraBinding::new(device),
+
+            position,
+            yaw: 0.,
+            pitch: 0.,
+            target_yaw: 0.,
+            target_pitch: 0.,
+            velocity: glm::Vec3::zeros(),
+            wish_dir: glm::Vec3::zeros(),
+            speed_multiplier: 1.0,
+        }
+    }
+
+    // Yaw 0 looks down -z like the arcball camera does from its default position
+    fn forward(&self) -> glm::Vec3 {
+        glm::vec3(-self.yaw.sin() * self.pitch.cos(), self.pitch.sin(), -self.yaw.cos() * self.pitch.cos())
+    }
+
+    pub fn look_at(&mut self, target: &glm::Vec3) {
+        let dir = glm::normalize(&(target - self.position));
+        self.yaw = (-dir.x).atan2(-dir.z);
+        self.pitch = dir.y.clamp(-1.0, 1.0).asin();
+        self.target_yaw = self.yaw;
+        self.target_pitch = self.pitch;
+    }
+}
+
+impl Camera for FlyCamera {
+    fn input(&mut self, input: &InputState) {
+        let look = input.value("look");
+        self.target_yaw -= look[0] * self.look_speed;
+        self.target_pitch = (self.target_pitch - look[1] * self.look_speed).clamp(-PI * 0.49, PI * 0.49);
+
+        let axis = |positive: &str, negative: &str| input.is_active(positive) as i32 as f32 - input.is_active(negative) as i32 as f32;
+        self.wish_dir = glm::vec3(axis("move_right", "move_left"), axis("move_up", "move_down"), axis("move_forward", "move_back"));
+        self.speed_multiplier = if input.is_active("move_fast") {
+            FLY_FAST_MULTIPLIER
+        } else if input.is_active("move_slow") {
+            FLY_SLOW_MULTIPLIER
+        } else {
+            1.0
+        };
+    }
+
+    fn tick(&mut self, time_delta: f32, queue: &Queue) {
+        // exponential smoothing, frame rate independent
+        let blend = 1.0 - (-FLY_SMOOTHING * time_delta).exp();
+        self.yaw += (self.target_yaw - self.yaw) * blend;
+        self.pitch += (self.target_pitch - self.pitch) * blend;
+
+        let forward = self.forward();
+        let right = glm::normalize(&glm::cross(&forward, &glm::vec3(0., 1., 0.)));
+        let mut wish_velocity = right * self.wish_dir.x + glm::vec3(0., 1., 0.) * self.wish_dir.y + forward * self.wish_dir.z;
+        if glm::length(&wish_velocity) > 0.0 {
+            wish_velocity = glm::normalize(&wish_velocity) * self.move_speed * self.speed_multiplier;
+        }
+        self.velocity += (wish_velocity - self.velocity) * blend;
+        self.position += self.velocity * time_delta;
+
+        let view = glm::look_at(&self.position, &(self.position + forward), &glm::vec3::<f32>(0., 1., 0.));
+        self.binding.write(queue, &self.lens, &view, &self.position);
+    }
+
+    // Backs off from the center of the box along the current view direction
+    fn frame_bounds(&mut self, bounds: &Aabb) {
+        let center = (bounds.min + bounds.max) * 0.5;
+        self.position = center - self.forward() * framing_distance(bounds, &self.lens);
+        self.velocity = glm::Vec3::zeros();
+    }
+
+    fn pose(&self) -> Pose {
+        Pose::from_yaw_pitch(self.position, self.yaw, self.pitch)
+    }
+
+    // Skips the smoothing, the path is already smooth
+    fn set_pose(&mut self, pose: &Pose) {
+        self.position = pose.position;
+        (self.yaw, self.pitch) = pose.yaw_pitch();
+        self.target_yaw = self.yaw;
+        self.target_pitch = self.pitch;
+        self.velocity = glm::Vec3::zeros();
+    }
+
+    fn frustum(&self) -> Frustum {
+        self.binding.frustum
+    }
+
+    fn bind_group(&self) -> &BindGroup {
+        &self.binding.camera_bind_group
+    }
+
+    fn projection(&self) -> Projection {
+        self.lens.projection
+    }
+
+    fn get_bind_group(&self, index: u32) -> (BindGroupLayoutEntry, BindGroupEntry<'_>) {
+        self.binding.get_bind_group(index)
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{app::{App, AppVariant}, assets_helper::ResourceManager, camera::Pose};
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Keyframe {
+    // seconds since the start of the path
+    pub time: f32,
+    pub position: [f32; 3],
+    // quaternion as x, y, z, w
+    pub orientation: [f32; 4],
+}
+
+impl Keyframe {
+    fn new(time: f32, pose: &Pose) -> Self {
+        let orientation = pose.orientation.coords;
+        Self {
+            time,
+            position: pose.position.into(),
+            orientation: [orientation.x, orientation.y, orientation.z, orientation.w],
+        }
+    }
+
+    fn position(&self) -> glm::Vec3 {
+        glm::make_vec3(&self.position)
+    }
+
+    fn orientation(&self) -> glm::Quat {
+        let [x, y, z, w] = self.orientation;
+        glm::quat_normalize(&glm::quat(x, y, z, w))
+    }
+}
+
+// Camera poses over time. Positions follow a Catmull-Rom spline through the keyframes,
+// orientations are slerped between neighbouring keyframes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraPath {
+    // starts over after the last keyframe instead of holding it
+    #[serde(default)]
+    pub looping: bool,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    // A json object like {"looping": true, "keyframes": [{"time": 0.0, "position": [0, 0, 1], "orientation": [0, 0, 0, 1]}]},
+    // or the same as RON when the file ends with .ron. Keyframes don't have to be sorted
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let camera_path: anyhow::Result<CameraPath> = if is_ron(path) {
+            ron::from_str(&text).map_err(Into::into)
+        } else {
+            serde_json::from_str(&text).map_err(Into::into)
+        };
+        let mut camera_path = camera_path.with_context(|| format!("Failed to parse {}", path.display()))?;
+        camera_path.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(camera_path)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let text = if is_ron(path) {
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    // Appends a keyframe, time has to be past the last one
+    pub fn record(&mut self, time: f32, pose: &Pose) {
+        self.keyframes.push(Keyframe::new(time, pose));
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    pub fn sample(&self, time: f32) -> Option<Pose> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        let time = if self.looping && self.duration() > 0.0 { time.rem_euclid(self.duration()) } else { time };
+        // index of the keyframe starting the segment time falls into
+        let segment = self.keyframes.partition_point(|keyframe| keyframe.time <= time).saturating_sub(1).min(last.saturating_sub(1));
+        let (start, end) = (&self.keyframes[segment], &self.keyframes[(segment + 1).min(last)]);
+        let t = if end.time > start.time { ((time - start.time) / (end.time - start.time)).clamp(0.0, 1.0) } else { 0.0 };
+
+        // the outer control points are repeated at the ends of the path
+        let p0 = self.keyframes[segment.saturating_sub(1)].position();
+        let p3 = self.keyframes[(segment + 2).min(last)].position();
+        let position = catmull_rom(&p0, &start.position(), &end.position(), &p3, t);
+
+        let (from, mut to) = (start.orientation(), end.orientation());
+        // q and -q are the same rotation, the closer one takes the short way around
+        if glm::quat_dot(&from, &to) < 0.0 {
+            to = -to;
+        }
+        Some(Pose { position, orientation: glm::quat_slerp(&from, &to, t) })
+    }
+}
+
+fn is_ron(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ron"))
+}
+
+fn catmull_rom(p0: &glm::Vec3, p1: &glm::Vec3, p2: &glm::Vec3, p3: &glm::Vec3, t: f32) -> glm::Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0 + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5
+}
+
+// What the runners use to play a path back through the app's camera and to record new ones.
+// Playback time advances with the frame delta, so headless runs and recorded sequences are reproducible.
+pub struct CameraPathDriver {
+    playback: Option<CameraPath>,
+    recording: Option<(PathBuf, CameraPath)>,
+    recording_start: f32,
+    time: f32,
+}
+
+impl CameraPathDriver {
+    pub fn from_variant(app_variant: &AppVariant) -> Self {
+        let playback = app_variant.camera_path.as_ref().and_then(|path| match CameraPath::load(path) {
+            Ok(camera_path) => Some(camera_path),
+            Err(error) => {
+                println!("Camera path won't be played - {:?}", error);
+                None
+            },
+        });
+        let recording = app_variant.record_camera_path.clone().map(|path| (path, CameraPath::default()));
+        Self { playback, recording, recording_start: 0.0, time: 0.0 }
+    }
+
+    // Moves the app's camera along the path, called after the input and before App::tick
+    pub fn advance<T: ResourceManager, A: App<T> + ?Sized>(&mut self, app_instance: &mut A, delta: f32) {
+        self.time += delta;
+        let Some(playback) = &self.playback else { return };
+        if let (Some(pose), Some(camera)) = (playback.sample(self.time), app_instance.camera()) {
+            camera.set_pose(&pose);
+        }
+    }
+
+    // Adds the camera's current pose as a keyframe and rewrites the recording, so nothing is lost when the app is killed
+    pub fn record_keyframe<T: ResourceManager, A: App<T> + ?Sized>(&mut self, app_instance: &mut A) {
+        let (Some((path, recording)), Some(camera)) = (&mut self.recording, app_instance.camera()) else { return };
+        // the first keyframe starts the path
+        if recording.keyframes.is_empty() {
+            self.recording_start = self.time;
+        }
+        let time = self.time - self.recording_start;
+        recording.record(time, &camera.pose());
+        match recording.save(path) {
+            Ok(()) => println!("Recorded camera keyframe {} at {:.2}s", recording.keyframes.len(), time),
+            Err(error) => println!("Failed to save the camera path - {:?}", error),
+        }
+    }
+}
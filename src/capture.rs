@@ -0,0 +1,205 @@
+use std::{fs, path::PathBuf, sync::mpsc::{self, SyncSender}, thread::{self, JoinHandle}, time::{SystemTime, UNIX_EPOCH}};
+
+use anyhow::Context;
+use image::RgbaImage;
+use wgpu::{Device, Queue, Texture, TextureFormat, TextureUsages, TextureView, RenderPipeline, BindGroup};
+
+use crate::{readback, render_target::RenderTarget};
+
+// How many frames may wait for png encoding before rendering blocks
+const WRITE_QUEUE_SIZE: usize = 8;
+
+struct Sequence {
+    dir: PathBuf,
+    frame: u32,
+}
+
+// Where captured frames are drawn, not every backend allows copying from surface textures.
+// The frame gets blitted to the surface afterwards so the window keeps showing it.
+struct Offscreen {
+    texture: Texture,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    blit_pipeline: RenderPipeline,
+    blit_bind_group: BindGroup,
+}
+
+impl Offscreen {
+    fn new(device: &Device, format: TextureFormat, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture target"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Capture blit bgl"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let blit_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Capture blit bg"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.create_view(&wgpu::TextureViewDescriptor::default())),
+            }],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Capture blit shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("pieces/shaders/wgsl/capture_blit.wgsl").into()),
+        });
+        let blit_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Capture blit pipeline"),
+            layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Capture blit pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            })),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self { texture, format, width, height, blit_pipeline, blit_bind_group }
+    }
+}
+
+// Saves frames the windowed runner renders: single screenshots or a numbered png sequence.
+// While a sequence is recorded the app has to be ticked with sequence_delta() so that the
+// frames are evenly spaced in time no matter how long rendering and encoding take.
+pub struct FrameCapture {
+    output_dir: PathBuf,
+    sequence_fps: f32,
+    screenshot_requested: bool,
+    sequence: Option<Sequence>,
+    offscreen: Option<Offscreen>,
+    writer: Option<SyncSender<(PathBuf, RgbaImage)>>,
+    writer_thread: Option<JoinHandle<()>>,
+}
+
+impl FrameCapture {
+    pub fn new(output_dir: PathBuf, sequence_fps: f32) -> Self {
+        let (writer, receiver) = mpsc::sync_channel::<(PathBuf, RgbaImage)>(WRITE_QUEUE_SIZE);
+        let writer_thread = thread::spawn(move || {
+            for (path, image) in receiver {
+                if let Err(error) = image.save(&path) {
+                    println!("Failed to write {} - {}", path.display(), error);
+                }
+            }
+        });
+        Self { output_dir, sequence_fps, screenshot_requested: false, sequence: None, offscreen: None, writer: Some(writer), writer_thread: Some(writer_thread) }
+    }
+
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    pub fn toggle_sequence(&mut self) {
+        match self.sequence.take() {
+            Some(sequence) => println!("Recorded {} frames to {}", sequence.frame, sequence.dir.display()),
+            None => {
+                let dir = self.output_dir.join(format!("sequence_{}", timestamp()));
+                println!("Recording {} fps sequence to {}", self.sequence_fps, dir.display());
+                self.sequence = Some(Sequence { dir, frame: 0 });
+            }
+        }
+    }
+
+    pub fn sequence_delta(&self) -> Option<f32> {
+        self.sequence.as_ref().map(|_| 1.0 / self.sequence_fps)
+    }
+
+    pub fn wants_frame(&self) -> bool {
+        self.screenshot_requested || self.sequence.is_some()
+    }
+
+    // What the app draws a wanted frame into instead of the surface, capture() reads it back afterwards
+    pub fn target(&mut self, device: &Device, format: TextureFormat, width: u32, height: u32) -> RenderTarget {
+        let offscreen = match self.offscreen.take() {
+            Some(offscreen) if (offscreen.format, offscreen.width, offscreen.height) == (format, width, height) => offscreen,
+            _ => Offscreen::new(device, format, width, height),
+        };
+        let offscreen = self.offscreen.insert(offscreen);
+        RenderTarget::from_texture(&offscreen.texture, format, width, height).captured()
+    }
+
+    // Saves the frame drawn into target() and copies it to the surface texture's view
+    pub fn capture(&mut self, device: &Device, queue: &Queue, surface_view: &TextureView) -> anyhow::Result<()> {
+        let offscreen = self.offscreen.as_ref().context("No frame was drawn for capture")?;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Capture blit encoder") });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Capture blit pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: surface_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&offscreen.blit_pipeline);
+            render_pass.set_bind_group(0, &offscreen.blit_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let mut paths = vec![];
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            paths.push(self.output_dir.join(format!("screenshot_{}.png", timestamp())));
+        }
+        if let Some(sequence) = self.sequence.as_mut() {
+            paths.push(sequence.dir.join(format!("frame_{:05}.png", sequence.frame)));
+            sequence.frame += 1;
+        }
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let mut image = readback::read_texture(device, queue, &offscreen.texture, offscreen.format, offscreen.width, offscreen.height)?;
+        // surfaces are presented opaque, whatever alpha the examples leave behind is meaningless
+        for pixel in image.pixels_mut() {
+            pixel[3] = 255;
+        }
+        for path in paths {
+            let dir = path.parent().unwrap();
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+            self.writer.as_ref().unwrap().send((path, image.clone())).context("Capture writer thread is gone")?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FrameCapture {
+    // lets the writer finish the queued frames when the window gets closed mid recording
+    fn drop(&mut self) {
+        self.writer.take();
+        if let Some(writer_thread) = self.writer_thread.take() {
+            writer_thread.join().ok();
+        }
+    }
+}
+
+fn timestamp() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis())
+}
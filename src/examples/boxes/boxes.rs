@@ -1,13 +1,17 @@
 use std::iter;
+use std::sync::Arc;
 
-use rand::{distributions::Uniform, prelude::Distribution};
+use rand::{distributions::Uniform, prelude::Distribution, rngs::StdRng, SeedableRng};
 use wgpu::{PrimitiveState, Face, MultisampleState, FragmentState, ColorTargetState, TextureFormat, VertexBufferLayout, VertexAttribute, util::{DeviceExt, BufferInitDescriptor}, BufferUsages, RenderPipeline, Queue, Buffer, ShaderModuleDescriptor, BindGroupLayout, include_spirv_raw, ShaderModule, VertexState, DepthStencilState, StencilState, DepthBiasState, RenderPassDepthStencilAttachment, Operations, TextureView, Sampler, BindGroupDescriptor, BindGroupEntry, BindGroup, ComputePipelineDescriptor, PipelineLayoutDescriptor, ComputePipeline, Features, BufferDescriptor, BindGroupLayoutDescriptor, BindGroupLayoutEntry, ShaderStages};
 
-use crate::{app::{App, ShaderType, AppVariant}, camera::{ArcballCamera, Camera}, assets_helper::ResourceManager, input_event::InputEvent, geometry_primitives::{CUBE_DATA, CUBE_INDICES, FLOOR_DATA, FLOOR_INDICES}};
+use crate::{app::{App, ShaderType, AppVariant}, camera::{ArcballCamera, Camera, CAMERA_BGL}, assets_helper::ResourceManager, input_state::InputState, geometry_primitives::{CUBE_DATA, CUBE_INDICES, FLOOR_DATA, FLOOR_INDICES}};
+use crate::render_target::RenderTarget;
 
 const SHADOW_TEX_SIZE: u32 = 1024u32;
 const SHADOW_WORKGROUP_SIZE: u32 = 16u32;
 const CELLS_CNT: u32 = 10u32;
+// Fixed so that every run (and the golden image tests) get the same boxes layout
+const BOXES_LAYOUT_SEED: u64 = 42;
 
 struct GlobalConstants {
     shadow_res:         [f32; 2],
@@ -19,7 +23,7 @@ struct GlobalConstants {
 }
 
 struct Renderer {
-    queue: Queue,
+    queue: Arc<Queue>,
     depth_tex_view: TextureView,
     shadow_tex_bind_group: BindGroup,
 
@@ -52,7 +56,7 @@ pub struct BoxesExample {
 }
 
 impl <T: ResourceManager> App<T> for BoxesExample {
-    fn get_extra_device_features(app_variant: AppVariant) -> Features {
+    fn get_extra_device_features(app_variant: &AppVariant) -> Features {
         let mut features = match app_variant.shader_type {
             ShaderType::WGSL => Features::empty(),
             ShaderType::SPIRV => Features::SPIRV_SHADER_PASSTHROUGH,
@@ -64,24 +68,11 @@ impl <T: ResourceManager> App<T> for BoxesExample {
     fn new(
         sc: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
-        queue: wgpu::Queue,
+        queue: Arc<Queue>,
         shader_type: ShaderType,
         _ : &T
     ) -> Self {
-        let camera_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            label: Some("camera_bind_group_layout"),
-        });
+        let camera_bind_group_layout = device.create_bind_group_layout(&CAMERA_BGL);
 
         let global_constants_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor{
             label: Some("light_pos_bind_group_layout"),
@@ -138,7 +129,7 @@ impl <T: ResourceManager> App<T> for BoxesExample {
             let z = (id%CELLS_CNT * 4) as f32;
             vec![x - 18., z - 18., 0.0, 0.0]
         }).collect();
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::seed_from_u64(BOXES_LAYOUT_SEED);
         let unif = Uniform::new_inclusive(-1.0, 1.0);
         for cells_center in instances.chunks_mut(4) {
             cells_center[2] += unif.sample(&mut rng) * 1.5;
@@ -238,18 +229,15 @@ impl <T: ResourceManager> App<T> for BoxesExample {
         }
     }
 
-    fn render(&mut self, surface: &wgpu::Surface, device: &wgpu::Device) -> Result<(), wgpu::SurfaceError> {
-        let output = surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
+    fn render(&mut self, target: &RenderTarget, device: &wgpu::Device, alpha: f32) {
+        self.constants.light_position = self.light_controller.interpolated_position(alpha);
         let mut encoder = device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
         
         encoder.push_debug_group("shadow pass");
+        let shadow_scope = target.begin_gpu_scope(&mut encoder, "Shadow compute");
         {
             let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Shadow") });
             cpass.set_pipeline(&self.renderer.shadow_compute_pipeline);
@@ -257,9 +245,11 @@ impl <T: ResourceManager> App<T> for BoxesExample {
             cpass.set_bind_group(1, &self.renderer.shadow_bind_group, &[]);
             cpass.dispatch_workgroups(self.renderer.work_group_count, self.renderer.work_group_count, 1);
         }
+        target.end_gpu_scope(&mut encoder, shadow_scope);
         encoder.pop_debug_group();
 
         encoder.push_debug_group("geometry render pass");
+        let geometry_scope = target.begin_gpu_scope(&mut encoder, "Geometry pass");
         {
             let globals = [self.constants.shadow_res[0], self.constants.shadow_res[1], 
                                       self.constants.light_position[0], self.constants.light_position[1],
@@ -272,7 +262,7 @@ impl <T: ResourceManager> App<T> for BoxesExample {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: &target.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -293,11 +283,12 @@ impl <T: ResourceManager> App<T> for BoxesExample {
                     stencil_ops: None,
                 })
             });
+            target.apply_viewport(&mut render_pass);
 
             render_pass.set_pipeline(&self.renderer.floor_render_pipeline);
             render_pass.set_vertex_buffer(0, self.renderer.floor_vertex_buffer.slice(..));
             render_pass.set_bind_group(0, &self.renderer.global_constants_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.camera.camera_bind_group, &[]);
+            render_pass.set_bind_group(1, self.camera.bind_group(), &[]);
             render_pass.set_bind_group(2, &self.renderer.shadow_tex_bind_group, &[]);
             render_pass.set_index_buffer(self.renderer.floor_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             render_pass.draw_indexed(0..self.renderer.floor_index_count, 0, 0..1);
@@ -308,26 +299,29 @@ impl <T: ResourceManager> App<T> for BoxesExample {
             render_pass.set_index_buffer(self.renderer.cube_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             render_pass.draw_indexed(0..self.renderer.cube_index_count, 0, 0..self.renderer.cube_instances_count);
         }
+        target.end_gpu_scope(&mut encoder, geometry_scope);
         encoder.pop_debug_group();
         
         self.renderer.queue.submit(iter::once(encoder.finish()));
-        output.present();
-        Ok(())
     }
 
-    fn process_input(&mut self, event: &InputEvent) -> bool {
-        // TODO re-enable
-        //self.light_controller.input(event);
-        self.camera.input(event);
-        false
+    fn process_input_state(&mut self, input: &InputState) {
+        self.light_controller.input(input);
+        self.camera.input(input);
+    }
+
+    fn camera(&mut self) -> Option<&mut dyn Camera> {
+        Some(&mut self.camera)
     }
 
     fn tick(&mut self, delta: f32) {
-        self.light_controller.tick(delta);
-        self.constants.light_position = self.light_controller.light_position;
         self.camera.tick(delta, &self.renderer.queue);
         self.constants.time_in_flight += delta;
     }
+
+    fn update(&mut self, step: f32) {
+        self.light_controller.tick(step);
+    }
 }
 
 impl BoxesExample {
@@ -671,6 +665,7 @@ impl BoxesExample {
 
 struct LightController {
     light_position: [f32; 2],
+    prev_light_position: [f32; 2],
     directions_pressed: [bool; 4],
     speed: f32,
 }
@@ -681,34 +676,28 @@ impl LightController {
     fn new(speed: f32) -> Self {
         Self {
             light_position: [0.5, 0.5],
+            prev_light_position: [0.5, 0.5],
             directions_pressed: [false, false, false, false],
             speed,
         }
     }
 
-    // TODO re-enable
-    // fn input(&mut self, event: &WindowEvent) {
-    //     match event {
-    //         WindowEvent::KeyboardInput {
-    //             input,
-    //             ..
-    //         } => {
-    //             let index = match input.virtual_keycode.unwrap() {
-    //                 VirtualKeyCode::A | VirtualKeyCode::Left => Some(Self::LEFT),
-    //                 VirtualKeyCode::W | VirtualKeyCode::Up => Some(Self::UP),
-    //                 VirtualKeyCode::D | VirtualKeyCode::Right => Some(Self::RIGHT),
-    //                 VirtualKeyCode::S | VirtualKeyCode::Down => Some(Self::DOWN),
-    //                 _ => None
-    //             };
-    //             if let Some(ind) = index {
-    //                 self.directions_pressed[ind] = input.state == ElementState::Pressed;
-    //             }
-    //         },
-    //         _ => {}
-    //     }
-    // }
+    fn input(&mut self, input: &InputState) {
+        self.directions_pressed[Self::LEFT] = input.is_active("move_left");
+        self.directions_pressed[Self::UP] = input.is_active("move_forward");
+        self.directions_pressed[Self::RIGHT] = input.is_active("move_right");
+        self.directions_pressed[Self::DOWN] = input.is_active("move_back");
+    }
+
+    fn interpolated_position(&self, alpha: f32) -> [f32; 2] {
+        [
+            self.prev_light_position[0] + (self.light_position[0] - self.prev_light_position[0]) * alpha,
+            self.prev_light_position[1] + (self.light_position[1] - self.prev_light_position[1]) * alpha,
+        ]
+    }
 
     fn tick(&mut self, time_delta: f32) {
+        self.prev_light_position = self.light_position;
         if self.directions_pressed[Self::LEFT] {
             self.light_position[0] -= time_delta * self.speed;
         }
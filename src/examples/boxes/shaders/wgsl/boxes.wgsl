@@ -8,7 +8,17 @@ struct GlobalConstants
     unused:             vec2<f32>
 };
 struct CameraUniform {
-    view_proj: mat4x4<f32>,
+    proj:       mat4x4<f32>,
+    model:      mat4x4<f32>,
+    view:       mat4x4<f32>,
+    position:   vec4<f32>,
+    view_proj:  mat4x4<f32>,
+    inv_proj:   mat4x4<f32>,
+    inv_view:   mat4x4<f32>,
+    viewport:   vec4<f32>,
+    near:       f32,
+    far:        f32,
+    jitter:     vec2<f32>,
 };
 struct VertexInput {
     @location(0) position: vec3<f32>,
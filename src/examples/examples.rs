@@ -0,0 +1,224 @@
+use std::{iter, sync::Arc, time::Duration};
+
+use wgpu::{Device, Features, Queue, SurfaceConfiguration};
+
+use crate::{app::{App, AppVariant, Example, ShaderType}, assets_helper::DesktopResourceManager, camera::Camera, imgui_context::{self, ImguiContext}, input_event::{InputEvent, EventType}, input_state::InputState, render_target::RenderTarget};
+use crate::{boxes::BoxesExample, flipboard::FlipboardExample, fullscreen_triangle::FullscreenTriangleExample, gltf_viewer::GLTFViewerExample,
+            imgui_example::ImGUIExample, pbr::PBRExample, shadow_mapping::ShadowMappingExample, skybox_example::SkyboxExample};
+
+const NEXT_EXAMPLE_KEY: char = ']';
+const PREV_EXAMPLE_KEY: char = '[';
+
+pub fn extra_device_features(example: Example, app_variant: &AppVariant) -> Features {
+    match example {
+        Example::Boxes => <BoxesExample as App<DesktopResourceManager>>::get_extra_device_features(app_variant),
+        Example::Flipboard => <FlipboardExample as App<DesktopResourceManager>>::get_extra_device_features(app_variant),
+        Example::FullscreenTriangle => <FullscreenTriangleExample as App<DesktopResourceManager>>::get_extra_device_features(app_variant),
+        Example::GLTFViewer => <GLTFViewerExample as App<DesktopResourceManager>>::get_extra_device_features(app_variant),
+        Example::ImGUI => <ImGUIExample as App<DesktopResourceManager>>::get_extra_device_features(app_variant),
+        Example::PBR => <PBRExample as App<DesktopResourceManager>>::get_extra_device_features(app_variant),
+        Example::ShadowMapping => <ShadowMappingExample as App<DesktopResourceManager>>::get_extra_device_features(app_variant),
+        Example::Skybox => <SkyboxExample as App<DesktopResourceManager>>::get_extra_device_features(app_variant),
+    }
+}
+
+pub fn create_example(example: Example, sc: &SurfaceConfiguration, device: &Device, queue: Arc<Queue>, app_variant: &AppVariant) -> Box<dyn App<DesktopResourceManager>> {
+    let resource_manager = DesktopResourceManager{};
+    match example {
+        Example::Boxes => Box::new(BoxesExample::from_variant(sc, device, queue, app_variant, &resource_manager)),
+        Example::Flipboard => Box::new(FlipboardExample::from_variant(sc, device, queue, app_variant, &resource_manager)),
+        Example::FullscreenTriangle => Box::new(FullscreenTriangleExample::from_variant(sc, device, queue, app_variant, &resource_manager)),
+        Example::GLTFViewer => Box::new(GLTFViewerExample::from_variant(sc, device, queue, app_variant, &resource_manager)),
+        Example::ImGUI => Box::new(ImGUIExample::from_variant(sc, device, queue, app_variant, &resource_manager)),
+        Example::PBR => Box::new(PBRExample::from_variant(sc, device, queue, app_variant, &resource_manager)),
+        Example::ShadowMapping => Box::new(ShadowMappingExample::from_variant(sc, device, queue, app_variant, &resource_manager)),
+        Example::Skybox => Box::new(SkyboxExample::from_variant(sc, device, queue, app_variant, &resource_manager)),
+    }
+}
+
+// Hosts one of the examples and swaps it for another one at runtime, picked from an imgui menu
+// drawn over the example or cycled through with ']' and '['.
+pub struct ExampleSelector {
+    current: Example,
+    pending: Option<Example>,
+    app_instance: Option<Box<dyn App<DesktopResourceManager>>>,
+    surface_config: SurfaceConfiguration,
+    queue: Arc<Queue>,
+    app_variant: AppVariant,
+    imgui_context: ImguiContext,
+    imgui_renderer: imgui_wgpu::Renderer,
+}
+
+impl App<DesktopResourceManager> for ExampleSelector {
+    fn get_extra_device_features(app_variant: &AppVariant) -> Features {
+        extra_device_features(app_variant.example, app_variant)
+    }
+
+    // whatever the other examples need, so switching to them works wherever the adapter allows
+    fn get_optional_device_features(app_variant: &AppVariant) -> Features {
+        Example::ALL.iter().fold(Features::empty(), |features, example| features | extra_device_features(*example, app_variant))
+    }
+
+    fn new(
+        sc: &SurfaceConfiguration,
+        device: &Device,
+        queue: Arc<Queue>,
+        shader_type: ShaderType,
+        resource_manager: &DesktopResourceManager
+    ) -> Self {
+        let app_variant = AppVariant { shader_type, ..AppVariant::default() };
+        Self::from_variant(sc, device, queue, &app_variant, resource_manager)
+    }
+
+    fn from_variant(
+        sc: &SurfaceConfiguration,
+        device: &Device,
+        queue: Arc<Queue>,
+        app_variant: &AppVariant,
+        _: &DesktopResourceManager
+    ) -> Self {
+        let (imgui_context, imgui_renderer) = ImguiContext::new(|imgui_context| {
+            imgui_wgpu::Renderer::new(imgui_context, device, &queue, imgui_wgpu::RendererConfig{
+                texture_format: sc.format,
+                ..Default::default()
+            })
+        });
+        let app_instance = create_example(app_variant.example, sc, device, queue.clone(), app_variant);
+        Self {
+            current: app_variant.example,
+            pending: None,
+            app_instance: Some(app_instance),
+            surface_config: sc.clone(),
+            queue,
+            app_variant: app_variant.clone(),
+            imgui_context,
+            imgui_renderer,
+        }
+    }
+
+    fn process_input(&mut self, event: &InputEvent) -> bool {
+        let target = self.pending.unwrap_or(self.current);
+        match event.event_type {
+            EventType::Char(NEXT_EXAMPLE_KEY) => self.pending = Some(target.next()),
+            EventType::Char(PREV_EXAMPLE_KEY) => self.pending = Some(target.prev()),
+            _ => {
+                let menu_hovered = self.imgui_context.with(|imgui_context| {
+                    imgui_context::handle_input(imgui_context.io_mut(), event);
+                    imgui_context.io().want_capture_mouse
+                });
+                // clicks and scrolls on the menu don't reach the example
+                if menu_hovered && matches!(event.event_type, EventType::Start | EventType::End | EventType::Wheel) {
+                    return true;
+                }
+                return self.app_instance.as_mut().map_or(false, |app| app.process_input(event));
+            },
+        }
+        true
+    }
+
+    fn process_input_state(&mut self, input: &InputState) {
+        if let Some(app) = self.app_instance.as_mut() {
+            app.process_input_state(input);
+        }
+    }
+
+    fn camera(&mut self) -> Option<&mut dyn Camera> {
+        self.app_instance.as_mut().and_then(|app| app.camera())
+    }
+
+    fn resize(&mut self, sc: &SurfaceConfiguration, device: &Device) {
+        self.surface_config = sc.clone();
+        if let Some(app) = self.app_instance.as_mut() {
+            app.resize(sc, device);
+        }
+    }
+
+    fn tick(&mut self, delta: f32) {
+        self.imgui_context.with(|imgui_context| imgui_context.io_mut().update_delta_time(Duration::from_secs_f32(delta)));
+        if let Some(app) = self.app_instance.as_mut() {
+            app.tick(delta);
+        }
+    }
+
+    fn update(&mut self, step: f32) {
+        if let Some(app) = self.app_instance.as_mut() {
+            app.update(step);
+        }
+    }
+
+    fn render(&mut self, target: &RenderTarget, device: &Device, alpha: f32) {
+        if let Some(example) = self.pending.take() {
+            self.switch_to(example, device);
+        }
+        if let Some(app) = self.app_instance.as_mut() {
+            app.render(target, device, alpha);
+        }
+        if !target.captured {
+            self.draw_menu(target, device);
+        }
+    }
+}
+
+impl ExampleSelector {
+    // Lists the examples over whatever the current one drew, a click switches on the next frame
+    fn draw_menu(&mut self, target: &RenderTarget, device: &Device) {
+        let current = self.pending.unwrap_or(self.current);
+        let mut selected = None;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Example menu encoder") });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Example menu pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+            target.apply_viewport(&mut render_pass);
+            self.imgui_context.with(|imgui_context| {
+                let size = target.size();
+                imgui_context.io_mut().display_size = [size[0] as f32, size[1] as f32];
+                let ui = imgui_context.frame();
+                // bottom left, out of the way of the examples' own windows
+                ui.window("Examples")
+                    .position([10.0, size[1] as f32 - 10.0], imgui::Condition::FirstUseEver)
+                    .position_pivot([0.0, 1.0])
+                    .always_auto_resize(true)
+                    .build(|| {
+                        for example in Example::ALL {
+                            if ui.selectable_config(example.name()).selected(example == current).build() {
+                                selected = Some(example);
+                            }
+                        }
+                    });
+                let draw_data = imgui_context.render();
+                // imgui skips drawing new windows on their first frame and hands out an empty (null) list
+                if draw_data.draw_lists_count() > 0 {
+                    self.imgui_renderer.render(draw_data, &self.queue, device, &mut render_pass).unwrap();
+                }
+            });
+        }
+        self.queue.submit(iter::once(encoder.finish()));
+        if selected.is_some() {
+            self.pending = selected;
+        }
+    }
+
+    fn switch_to(&mut self, example: Example, device: &Device) {
+        if example == self.current {
+            return;
+        }
+        // the device only has the other examples' features the adapter supports
+        let missing_features = extra_device_features(example, &self.app_variant) - device.features();
+        if !missing_features.is_empty() {
+            println!("Can't switch to {} example, device lacks {:?}", example, missing_features);
+            return;
+        }
+        // the old example's resources go before the new one's get created
+        self.app_instance = None;
+        self.app_instance = Some(create_example(example, &self.surface_config, device, self.queue.clone(), &self.app_variant));
+        self.current = example;
+        println!("Switched to {} example", example);
+    }
+}
@@ -4,6 +4,8 @@ use glm::{Vec2, UVec2, distance};
 use ncollide2d::{shape::{ShapeHandle, Plane, Ball, Cuboid}, na::{Vector2, Isometry2, self}, pipeline::{CollisionGroups, GeometricQueryType, CollisionObjectSlabHandle, ContactEvent}, world::CollisionWorld};
 use wgpu::{Buffer, Device, util::{DeviceExt, BufferInitDescriptor}, BufferUsages, BindGroupLayoutEntry, BindGroupEntry, Queue};
 
+use crate::input_state::InputState;
+
 use super::FlapPad;
 
 type World = CollisionWorld<f32, CollisionObjectData>;
@@ -117,7 +119,7 @@ impl Arkanoid {
     }
 
     fn add_gamepad(world: &mut World, contacts_query: GeometricQueryType<f32>) -> CollisionObjectSlabHandle {
-        let game_pad = ShapeHandle::new(Cuboid::new(Vector2::new(Self::GAMEPAD_SIZE.x, Self::GAMEPAD_SIZE.y)));;
+        let game_pad = ShapeHandle::new(Cuboid::new(Vector2::new(Self::GAMEPAD_SIZE.x, Self::GAMEPAD_SIZE.y)));
         let pos = Vector2::new(0.5, Self::GAMEPAD_SIZE.y);
         let gamepad_pos = Isometry2::new(pos, na::zero());
         
@@ -147,7 +149,7 @@ impl Arkanoid {
         res
     }
     
-    pub fn get_bind_group(&self, index: u32) -> (BindGroupLayoutEntry, BindGroupEntry) {
+    pub fn get_bind_group(&self, index: u32) -> (BindGroupLayoutEntry, BindGroupEntry<'_>) {
         (
             wgpu::BindGroupLayoutEntry {
                 binding: index,
@@ -166,18 +168,23 @@ impl Arkanoid {
         )
     }
 
-    pub fn tick(&mut self, delta: f32) {
+    // Moves the game objects, meant to be called with a fixed step
+    pub fn simulate(&mut self, step: f32) {
         if self.game_state == GameState::Running {
-            self.physics_step(delta);
+            self.physics_step(step);
         }
-        
+        if self.game_state == GameState::Ready || self.game_state == GameState::Running {
+            self.update_game_pad(step);
+        }
+    }
+
+    // Rasterizes the game into the flaps and animates them
+    pub fn tick(&mut self, delta: f32) {
         match self.game_state {
             GameState::Ended => {
                 self.show_text("START GAME", [11, 7]);
             },
             GameState::Ready | GameState::Running => {
-                self.update_game_pad(delta);
-
                 let ball_object   = self.world.collision_object(self.ball).unwrap();
                 let ball_pos: Vec2 = Vec2::new(ball_object.position().translation.vector[0] * Self::ASPECT.x, ball_object.position().translation.vector[1] * Self::ASPECT.y);
 
@@ -205,7 +212,6 @@ impl Arkanoid {
                     })
                 });
             },
-            _ => ()
         }
 
         for cur_cell in self.output_cells.chunks_mut(2) {
@@ -218,7 +224,7 @@ impl Arkanoid {
                 }
                 cur_cell[0] = cur_cell[0].floor() + new_val;
             } else  if cur_cell[0] != cur_cell[1] {
-                cur_cell[0] += (delta * Self::FLAP_SPEED);
+                cur_cell[0] += delta * Self::FLAP_SPEED;
             }
         }
     }
@@ -227,42 +233,25 @@ impl Arkanoid {
         queue.write_buffer(&self.gamedata_buffer, 0, bytemuck::cast_slice(&self.output_cells));
     }
 
-    // TODO re-enable
-    // pub fn input(&mut self, event: &WindowEvent) {
-    //     match event {
-    //         WindowEvent::KeyboardInput {
-    //             input,
-    //             ..
-    //         } => {
-    //             match self.game_state {
-    //                 GameState::Ended => {
-    //                     if input.virtual_keycode.unwrap() == VirtualKeyCode::Space &&
-    //                        input.state ==  ElementState::Released {
-    //                         self.clear();
-    //                         self.game_state = GameState::Running;
-    //                     }
-    //                 },
-    //                 GameState::Running => {
-    //                     let index = match input.virtual_keycode.unwrap() {
-    //                         VirtualKeyCode::A | VirtualKeyCode::Left => Some(Self::LEFT),
-    //                         VirtualKeyCode::D | VirtualKeyCode::Right => Some(Self::RIGHT),
-    //                         _ => None
-    //                     };
-    //                     if let Some(ind) = index {
-    //                         self.directions_pressed[ind] = input.state == ElementState::Pressed;
-    //                     }
-    //                 },
-    //                 GameState::Ready => {
-    //                     if input.virtual_keycode.unwrap() == VirtualKeyCode::Space &&
-    //                        input.state ==  ElementState::Released{
-    //                         self.game_state = GameState::Running;
-    //                     }
-    //                 },
-    //             };
-    //         },
-    //         _ => {}
-    //     }
-    // }
+    pub fn input(&mut self, input: &InputState) {
+        match self.game_state {
+            GameState::Ended => {
+                if input.was_released("launch") {
+                    self.clear();
+                    self.game_state = GameState::Running;
+                }
+            },
+            GameState::Running => {
+                self.directions_pressed[Self::LEFT] = input.is_active("move_left");
+                self.directions_pressed[Self::RIGHT] = input.is_active("move_right");
+            },
+            GameState::Ready => {
+                if input.was_released("launch") {
+                    self.game_state = GameState::Running;
+                }
+            },
+        };
+    }
 
     fn clear(&mut self) {
         for cur_cell in self.output_cells.chunks_mut(2) {
@@ -303,7 +292,8 @@ impl Arkanoid {
         if self.directions_pressed[Self::RIGHT] {
             speed += Self::GAMEPAD_SPEED;
         }
-        self.world.set_position(self.gamepad, Isometry2::new(Vector2::new(gamepad_pos.translation.vector[0] + delta * speed, gamepad_pos.translation.vector[1]), na::zero()));
+        let gamepad_pos = Isometry2::new(Vector2::new(gamepad_pos.translation.vector[0] + delta * speed, gamepad_pos.translation.vector[1]), na::zero());
+        self.world.get_mut(self.gamepad).unwrap().set_position(gamepad_pos);
     }
 
     fn handle_contact_event(world: &World, event: &ContactEvent<CollisionObjectSlabHandle>) -> Option<CollisionResult>{
@@ -369,7 +359,8 @@ impl Arkanoid {
                 CollisionObjectData::Ball { ref velocity } => {
                     //let ball_velocity = ball_object.data().velocity.as_ref().unwrap();
                     let ball_pos = ball_object.position();
-                    self.world.set_position(self.ball, Isometry2::new(ball_pos.translation.vector + delta * velocity.get(), na::zero()));    
+                    let ball_pos = Isometry2::new(ball_pos.translation.vector + delta * velocity.get(), na::zero());
+                    self.world.get_mut(self.ball).unwrap().set_position(ball_pos);
                 },
                 _ => ()
             }
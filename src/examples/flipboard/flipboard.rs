@@ -1,8 +1,10 @@
 use std::{iter, mem};
+use std::sync::Arc;
 use image::GenericImageView;
 use wgpu::{Queue, RenderPipeline, ColorTargetState, TextureFormat, ShaderModule, VertexState, FragmentState, Device, ShaderModuleDescriptor, PipelineLayoutDescriptor, PrimitiveState, MultisampleState, TextureView, BindGroupEntry, BindGroupDescriptor, BindGroup, BindGroupLayoutDescriptor, BindGroupLayoutEntry, ShaderStages, util::{BufferInitDescriptor, DeviceExt}, BufferUsages, Buffer, VertexBufferLayout, VertexAttribute, BufferDescriptor, BindGroupLayout, RenderPassDepthStencilAttachment, Operations, DepthStencilState, StencilState, DepthBiasState, RenderPipelineDescriptor, Sampler, BindingType};
 
-use crate::{app::{App, ShaderType}, camera::{ArcballCamera, Camera}, assets_helper::{Mesh, ResourceManager}, input_event::InputEvent};
+use crate::{app::{App, ShaderType}, camera::{ArcballCamera, Camera}, assets_helper::{Mesh, ResourceManager}, input_state::InputState};
+use crate::render_target::RenderTarget;
 
 const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
 const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
@@ -11,7 +13,7 @@ const SHADOW_TEX_SIZE: u32 = 1024;
 mod arkanoid;
 
 struct Renderer {
-    queue: Queue,
+    queue: Arc<Queue>,
     depth_tex_view:                     TextureView,
     shadow_tex_view:                    TextureView,
     constants_buffer:                   Buffer,
@@ -36,7 +38,7 @@ impl<T: ResourceManager> App<T> for FlipboardExample {
     fn new(
         sc: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
-        queue: wgpu::Queue,
+        queue: Arc<Queue>,
         shader_type: crate::app::ShaderType,
         resource_manager: &T
     ) -> Self {
@@ -163,14 +165,9 @@ impl<T: ResourceManager> App<T> for FlipboardExample {
         }
     }
 
-    fn render(&mut self, surface: &wgpu::Surface, device: &wgpu::Device) -> Result<(), wgpu::SurfaceError> {
+    fn render(&mut self, target: &RenderTarget, device: &wgpu::Device, _alpha: f32) {
         self.arkanoid.update(&self.renderer.queue);
 
-        let output = surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
         let mut encoder = device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
@@ -209,7 +206,7 @@ impl<T: ResourceManager> App<T> for FlipboardExample {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: &target.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -230,6 +227,7 @@ impl<T: ResourceManager> App<T> for FlipboardExample {
                     stencil_ops: None,
                 })
             });
+            target.apply_viewport(&mut render_pass);
         
             render_pass.set_pipeline(&self.renderer.flaps_pipeline);
             render_pass.set_bind_group(0, &self.renderer.global_data_bindgroup, &[]);
@@ -245,10 +243,8 @@ impl<T: ResourceManager> App<T> for FlipboardExample {
         }
         
         self.renderer.queue.submit(iter::once(encoder.finish()));
-        output.present();
         
         self.globals.time_delta = 0.0;
-        Ok(())
     }
     
     fn tick(&mut self, delta: f32) {
@@ -259,11 +255,17 @@ impl<T: ResourceManager> App<T> for FlipboardExample {
         self.globals.time_delta += delta;// * 0.1;
     }
 
-    fn process_input(&mut self, event: &InputEvent) -> bool {
-        // TODO re-enable
-        //self.arkanoid.input(event);
-        //self.camera.input(event)
-        false
+    fn update(&mut self, step: f32) {
+        self.arkanoid.simulate(step);
+    }
+
+    fn process_input_state(&mut self, input: &InputState) {
+        self.arkanoid.input(input);
+        self.camera.input(input);
+    }
+
+    fn camera(&mut self) -> Option<&mut dyn Camera> {
+        Some(&mut self.camera)
     }
 }
 
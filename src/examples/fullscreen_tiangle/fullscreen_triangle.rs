@@ -1,24 +1,26 @@
 use std::iter;
+use std::sync::Arc;
 use wgpu::{PrimitiveState, Face, MultisampleState, FragmentState, ColorTargetState, TextureFormat, Queue, include_spirv_raw, ShaderModule, ShaderModuleDescriptor, ShaderStages, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindGroupEntry, util::DeviceExt, BindGroupDescriptor, BindGroup, Buffer, VertexState};
 use crate::{app::App, app::ShaderType, assets_helper::ResourceManager};
+use crate::render_target::RenderTarget;
 
 pub struct Renderer {
     pipeline: wgpu::RenderPipeline,
     uniform_buffer: Buffer,
     uniform_bindgroup: BindGroup,
-    queue: Queue,
+    queue: Arc<Queue>,
 }
 
 pub struct FullscreenTriangleExample {
     renderer : Renderer,
-    resolution : Option<[u32; 2]>
+    resolution : [u32; 2]
 }
 
 impl<T: ResourceManager> App<T> for FullscreenTriangleExample{
     fn new(
         sc: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
-        queue: Queue,
+        queue: Arc<Queue>,
         shader_type: ShaderType,
         _: &T
     ) -> Self {
@@ -66,35 +68,27 @@ impl<T: ResourceManager> App<T> for FullscreenTriangleExample{
             queue
         };
 
-        Self{renderer, resolution: None}
+        Self{renderer, resolution: [sc.width, sc.height]}
     }
 
-    fn resize(&mut self, sc: &wgpu::SurfaceConfiguration, _device: &wgpu::Device) {
-        self.resolution = Some([sc.width, sc.height]);
-    }
-
-    fn render(&mut self, surface: &wgpu::Surface, device: &wgpu::Device) -> Result<(), wgpu::SurfaceError> {
-        let output = surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
+    fn render(&mut self, target: &RenderTarget, device: &wgpu::Device, _alpha: f32) {
         let mut encoder = device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
         
         {
-            if let Some(res) = self.resolution {
-                let resolution = [res[0], res[1], 0, 0];
+            let size = target.size();
+            if self.resolution != size {
+                let resolution = [size[0], size[1], 0, 0];
                 self.renderer.queue.write_buffer(&self.renderer.uniform_buffer, 0, bytemuck::cast_slice(&[resolution]));
-                self.resolution = None;
+                self.resolution = size;
             }
             
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: &target.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -108,6 +102,7 @@ impl<T: ResourceManager> App<T> for FullscreenTriangleExample{
                 })],
                 depth_stencil_attachment: None
             });
+            target.apply_viewport(&mut render_pass);
         
             render_pass.set_bind_group(0, &self.renderer.uniform_bindgroup, &[]);
             render_pass.set_pipeline(&self.renderer.pipeline);
@@ -115,9 +110,6 @@ impl<T: ResourceManager> App<T> for FullscreenTriangleExample{
         }
         
         self.renderer.queue.submit(iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
     }
 }
 
@@ -1,13 +1,17 @@
 use std::{iter, mem};
+use std::sync::Arc;
 
-use wgpu::{Queue, TextureFormat, VertexBufferLayout, VertexAttribute, ColorTargetState, VertexState, FragmentState, ShaderModule, PrimitiveState, Face, DepthStencilState, StencilState, DepthBiasState, MultisampleState, ShaderModuleDescriptor, RenderPipeline, RenderPassDepthStencilAttachment, Operations, TextureView, BindGroup, Buffer, BindGroupLayout};
+use wgpu::{Queue, TextureFormat, VertexBufferLayout, VertexAttribute, ColorTargetState, VertexState, FragmentState, ShaderModule, PrimitiveState, Face, DepthStencilState, StencilState, DepthBiasState, MultisampleState, ShaderModuleDescriptor, RenderPassDepthStencilAttachment, Operations, TextureView, BindGroup, Buffer, BindGroupLayout, PrimitiveTopology, CompareFunction};
 //use winit::event::WindowEvent;
 
-use crate::{app::{App, ShaderType}, camera::{ArcballCamera, Camera}, model::{GLTFModel, Drawable, NOD_MM_BGL, MATERIAL_BGL, parse_gltf}, assets_helper::ResourceManager, input_event::InputEvent};
+use crate::{app::{App, ShaderType}, camera::{ArcballCamera, Camera, FlyCamera, Projection, CAMERA_BGL}, model::{GLTFModel, Drawable, ModelPipelines, SceneCamera, NOD_MM_BGL, MATERIAL_BGL, parse_gltf}, assets_helper::ResourceManager, input_state::InputState};
+use crate::render_target::RenderTarget;
 struct Renderer {
-    queue: Queue,
+    queue: Arc<Queue>,
     
-    pipeline: RenderPipeline,
+    pipelines: ModelPipelines,
+    // for cameras with a reverse-Z projection
+    reverse_z_pipelines: ModelPipelines,
     depth_tex_view: TextureView,
 
     light_buffer: Buffer,
@@ -17,21 +21,28 @@ struct Renderer {
 pub struct GLTFViewerExample {
     renderer: Renderer,
     model: GLTFModel,
-    camera: ArcballCamera,
+    // perspective arcball, fly, orthographic and reverse-Z arcball camera followed by the ones in the file, toggled with C
+    cameras: Vec<Box<dyn Camera>>,
+    active_camera: usize,
     time_in_flight: f32,
 }
 
 const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
+// cameras that come before the model's ones
+const BUILTIN_CAMERAS: usize = 4;
 
 impl<T: ResourceManager> App<T> for GLTFViewerExample {
     fn new(
         sc: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
-        queue: wgpu::Queue,
+        queue: Arc<Queue>,
         shader_type: ShaderType,
         resource_manager: &T
     ) -> Self {
-        let model = pollster::block_on(parse_gltf("models/FlightHelmet/glTF/FlightHelmet.gltf", &device, &queue, resource_manager));
+        let model = pollster::block_on(parse_gltf("models/FlightHelmet/glTF/FlightHelmet.gltf", &device, &queue, resource_manager)).unwrap_or_else(|error| {
+            println!("Failed to load the model - {:?}", error);
+            GLTFModel::empty(device)
+        });
 
         let (light_bind_group_layout, light_bind_group, light_buffer) = {
             let light_uniform_size = mem::size_of::<LightData>() as wgpu::BufferAddress;
@@ -69,23 +80,34 @@ impl<T: ResourceManager> App<T> for GLTFViewerExample {
             (light_bind_group_layout, light_bind_group, light_buf)
         };
 
-        let pipeline = Self::create_output_pipeline(&device, sc.format, &light_bind_group_layout, shader_type);
+        let pipelines = ModelPipelines::new(&model, |topology| Self::create_output_pipeline(&device, sc.format, &light_bind_group_layout, topology, Projection::Perspective.depth_compare(), shader_type));
+        let reverse_z_pipelines = ModelPipelines::new(&model, |topology| Self::create_output_pipeline(device, sc.format, &light_bind_group_layout, topology, Projection::ReverseZInfinite.depth_compare(), shader_type));
         let depth_tex_view = Self::create_depth_texture(sc, device);
-        let renderer = Renderer { queue, pipeline, depth_tex_view, light_bind_group, light_buffer };
-        let camera = ArcballCamera::new(&device, sc.width as f32, sc.height as f32, 45., 0.01, 200., 7., 1.);
-        Self{ renderer, model, camera, time_in_flight: 0.0 }
+        let renderer = Renderer { queue, pipelines, reverse_z_pipelines, depth_tex_view, light_bind_group, light_buffer };
+        let arcball_camera = ArcballCamera::new(&device, sc.width as f32, sc.height as f32, 45., 0.01, 200., 7., 1.);
+        let mut fly_camera = FlyCamera::new(&device, sc.width as f32, sc.height as f32, 45., 0.01, 200., 0.005, 1., glm::vec3(0., 0., 1.));
+        fly_camera.look_at(&glm::vec3(0., -0.125, 0.));
+        let mut ortho_camera = ArcballCamera::new(&device, sc.width as f32, sc.height as f32, 45., 0.01, 200., 7., 1.);
+        ortho_camera.lens.projection = Projection::Orthographic { height: 0.5 };
+        let mut reverse_z_camera = ArcballCamera::new(device, sc.width as f32, sc.height as f32, 45., 0.01, 200., 7., 1.);
+        reverse_z_camera.lens.projection = Projection::ReverseZInfinite;
+        let mut cameras: Vec<Box<dyn Camera>> = vec![Box::new(arcball_camera), Box::new(fly_camera), Box::new(ortho_camera), Box::new(reverse_z_camera)];
+        cameras.extend(model.cameras().iter().map(|scene_camera| Box::new(Self::create_scene_camera(device, sc, scene_camera)) as Box<dyn Camera>));
+        Self{ renderer, model, cameras, active_camera: 0, time_in_flight: 0.0 }
     }
 
-    fn render(&mut self, surface: &wgpu::Surface, device: &wgpu::Device) -> Result<(), wgpu::SurfaceError> {
-        self.camera.tick(0.01, &self.renderer.queue);
-        let light_data = Self::get_light_matrix(self.time_in_flight);
+    fn render(&mut self, target: &RenderTarget, device: &wgpu::Device, _alpha: f32) {
+        let projection = self.cameras[self.active_camera].projection();
+        let pipelines = match projection {
+            Projection::ReverseZInfinite => &self.renderer.reverse_z_pipelines,
+            _ => &self.renderer.pipelines,
+        };
+        // a node named Light carries the light, it circles the model otherwise
+        let light_position = self.model.find_node("Light").and_then(|node| self.model.world_transform(node))
+            .map_or_else(|| Self::get_light_position(self.time_in_flight), |transform| transform.column(3).xyz());
+        let light_data = Self::get_light_matrix(&light_position);
         self.renderer.queue.write_buffer(&self.renderer.light_buffer, 0, bytemuck::cast_slice(&[light_data]));
         
-        let output = surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
         let mut encoder = device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
@@ -95,7 +117,7 @@ impl<T: ResourceManager> App<T> for GLTFViewerExample {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: &target.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -110,42 +132,74 @@ impl<T: ResourceManager> App<T> for GLTFViewerExample {
                 depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
                     view: &self.renderer.depth_tex_view,
                     depth_ops: Some(Operations{
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: wgpu::LoadOp::Clear(projection.depth_clear()),
                         store: false,
                     }),
                     stencil_ops: None,
                 })
             });
+            target.apply_viewport(&mut render_pass);
         
-            render_pass.set_pipeline(&self.renderer.pipeline);
-            render_pass.set_bind_group(0, &self.camera.camera_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.renderer.light_bind_group, &[]);
-            render_pass.draw_model(&self.model, 2);
+            render_pass.set_bind_group(0, self.cameras[self.active_camera].bind_group(), &[]);
+            render_pass.set_bind_group(3, &self.renderer.light_bind_group, &[]);
+            let stats = render_pass.draw_model(&self.model, pipelines, 2, &self.cameras[self.active_camera].frustum());
+            target.count("primitives visible", stats.visible);
+            target.count("primitives culled", stats.culled);
         }
         
         self.renderer.queue.submit(iter::once(encoder.finish()));
-        output.present();
+    }
 
-        Ok(())
+    fn process_input_state(&mut self, input: &InputState) {
+        if input.was_released("toggle_camera") {
+            self.active_camera = (self.active_camera + 1) % self.cameras.len();
+            if let Some(scene_camera) = self.active_camera.checked_sub(BUILTIN_CAMERAS).and_then(|index| self.model.cameras().get(index)) {
+                println!("Switched to the {} camera of the model", scene_camera.name.as_deref().unwrap_or("unnamed"));
+            }
+        }
+        if input.was_released("frame") {
+            self.cameras[self.active_camera].frame_bounds(&self.model.bounds());
+        }
+        if input.was_released("toggle_animation") {
+            self.model.set_playing(!self.model.playback().playing);
+        }
+        if input.was_released("next_animation") && !self.model.animations().is_empty() {
+            let animation = (self.model.playback().animation + 1) % self.model.animations().len();
+            self.model.play(animation);
+        }
+        self.cameras[self.active_camera].input(input);
     }
 
-    fn process_input(&mut self, event: &InputEvent) -> bool {
-        self.camera.input(event);
-        false
+    fn camera(&mut self) -> Option<&mut dyn Camera> {
+        Some(self.cameras[self.active_camera].as_mut())
     }
 
     fn tick(&mut self, delta: f32) {
-        self.camera.tick(delta, &self.renderer.queue);
+        self.cameras[self.active_camera].tick(delta, &self.renderer.queue);
+        self.model.update(&self.renderer.queue, delta);
         self.time_in_flight += delta;
     }
 }
 
 impl GLTFViewerExample {
-    fn create_output_pipeline(device: &wgpu::Device, tex_format: TextureFormat, light_bind_group_layout: &BindGroupLayout, /*shadow_tex_view: &TextureView, shadow_sampler: &Sampler,*/ shader_type: ShaderType) -> wgpu::RenderPipeline {
+    // Starts out where the file puts the camera and can be flown around from there
+    fn create_scene_camera(device: &wgpu::Device, sc: &wgpu::SurfaceConfiguration, scene_camera: &SceneCamera) -> FlyCamera {
+        let mut camera = FlyCamera::new(device, sc.width as f32, sc.height as f32, scene_camera.yfov, scene_camera.znear,
+                                        scene_camera.zfar.unwrap_or(200.), 0.005, 1., scene_camera.pose.position);
+        // perspective cameras without a far plane are infinite ones
+        camera.lens.projection = match (scene_camera.projection, scene_camera.zfar) {
+            (Projection::Perspective, None) => Projection::ReverseZInfinite,
+            (projection, _) => projection,
+        };
+        camera.set_pose(&scene_camera.pose);
+        camera
+    }
+
+    fn create_output_pipeline(device: &wgpu::Device, tex_format: TextureFormat, light_bind_group_layout: &BindGroupLayout, /*shadow_tex_view: &TextureView, shadow_sampler: &Sampler,*/ topology: PrimitiveTopology, depth_compare: CompareFunction, shader_type: ShaderType) -> wgpu::RenderPipeline {
         let buffer_layout = 
         [
             VertexBufferLayout{
-                array_stride: std::mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                array_stride: std::mem::size_of::<[f32; 26]>() as wgpu::BufferAddress,
                 step_mode: wgpu::VertexStepMode::Vertex,
                 attributes: &[VertexAttribute{
                     format: wgpu::VertexFormat::Float32x3,
@@ -154,18 +208,33 @@ impl GLTFViewerExample {
                 },
                 VertexAttribute{
                     format: wgpu::VertexFormat::Float32x2,
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
                     shader_location: 1,
                 },
                 VertexAttribute{
                     format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 2,
                 },
                 VertexAttribute{
                     format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    offset: std::mem::size_of::<[f32; 10]>() as wgpu::BufferAddress,
                     shader_location: 3,
+                },
+                VertexAttribute{
+                    format: wgpu::VertexFormat::Uint32x4,
+                    offset: std::mem::size_of::<[f32; 17]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                },
+                VertexAttribute{
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 21]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                },
+                VertexAttribute{
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: std::mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
+                    shader_location: 6,
                 }]
             }
         ];
@@ -202,25 +271,12 @@ impl GLTFViewerExample {
             _ => panic!()
         }
         
-        let camera_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            label: Some("camera_bind_group_layout"),
-        });
+        let camera_bind_group_layout = device.create_bind_group_layout(&CAMERA_BGL);
 
         let pipeline_layout = device.create_pipeline_layout(
             &wgpu::PipelineLayoutDescriptor {
                 label: Some("Output pipeline layout"),
-                bind_group_layouts: &[&camera_bind_group_layout, &light_bind_group_layout, &device.create_bind_group_layout(&NOD_MM_BGL), &device.create_bind_group_layout(&MATERIAL_BGL),],
+                bind_group_layouts: &[&camera_bind_group_layout, &device.create_bind_group_layout(&MATERIAL_BGL), &device.create_bind_group_layout(&NOD_MM_BGL), &light_bind_group_layout,],
                 push_constant_ranges: &[],
             }
         );
@@ -229,7 +285,7 @@ impl GLTFViewerExample {
             layout: Some(&pipeline_layout),
             vertex: vertex_state,
             primitive: PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
+                topology,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
                 cull_mode: Some(Face::Back),
@@ -240,7 +296,7 @@ impl GLTFViewerExample {
             depth_stencil: Some(DepthStencilState{
                 format: DEPTH_FORMAT,
                 depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::LessEqual,
+                depth_compare,
                 stencil: StencilState::default(),
                 bias: DepthBiasState::default(),
             }),
@@ -272,11 +328,14 @@ impl GLTFViewerExample {
         depth_texture.create_view(&wgpu::TextureViewDescriptor::default())
     }
 
-    fn get_light_matrix(time: f32) -> LightData {
+    fn get_light_position(time: f32) -> glm::Vec3 {
         let distance = 10.;
 
-        let light_position = glm::Vec3::new(time.sin() * distance, 10., time.cos() * distance);
-        let light_view_matrix = glm::look_at(&light_position, &glm::Vec3::new(0.0, 0.0, 0.0), &glm::Vec3::new(0.0, 1.0, 0.0));
+        glm::Vec3::new(time.sin() * distance, 10., time.cos() * distance)
+    }
+
+    fn get_light_matrix(light_position: &glm::Vec3) -> LightData {
+        let light_view_matrix = glm::look_at(light_position, &glm::Vec3::new(0.0, 0.0, 0.0), &glm::Vec3::new(0.0, 1.0, 0.0));
         let light_proj_matrix = glm::ortho(-0.5, 0.5, -0.5, 0.5, -15., 15.);
         
         LightData {
@@ -1,6 +1,15 @@
 struct CameraUniform {
-    view_proj: mat4x4<f32>,
-    position: vec4<f32>,
+    proj:       mat4x4<f32>,
+    model:      mat4x4<f32>,
+    view:       mat4x4<f32>,
+    position:   vec4<f32>,
+    view_proj:  mat4x4<f32>,
+    inv_proj:   mat4x4<f32>,
+    inv_view:   mat4x4<f32>,
+    viewport:   vec4<f32>,
+    near:       f32,
+    far:        f32,
+    jitter:     vec2<f32>,
 };
 struct LightUniform {
     view_proj: mat4x4<f32>,
@@ -11,6 +20,21 @@ struct VertexInput {
     @location(1) uv: vec2<f32>,
     @location(2) normal: vec3<f32>,
     @location(3) color: vec3<f32>,
+    @location(4) joints: vec4<u32>,
+    @location(5) weights: vec4<f32>,
+    @location(6) morph_index: u32,
+};
+struct NodeUniform {
+    transform: mat4x4<f32>,
+    joint_count: u32,
+    morph_target_count: u32,
+    joint_offset: u32,
+    morph_weights: array<vec4<f32>, 2>,
+};
+struct MorphDelta {
+    position: vec4<f32>,
+    normal: vec4<f32>,
+    tangent: vec4<f32>,
 };
 struct VertexOutput {
     @builtin(position) clip_pos: vec4<f32>,
@@ -22,17 +46,35 @@ struct VertexOutput {
 };
 
 @group(0) @binding(0) var<uniform> camera: CameraUniform;
-@group(1) @binding(0) var<uniform> light: LightUniform;
-@group(2) @binding(0) var<uniform> node_matrix: mat4x4<f32>;
-@group(3) @binding(0) var t_diffuse_tex: texture_2d<f32>;
-@group(3) @binding(1) var s_diffuse_tex: sampler;
+@group(1) @binding(0) var t_diffuse_tex: texture_2d<f32>;
+@group(1) @binding(1) var s_diffuse_tex: sampler;
+@group(2) @binding(0) var<uniform> node: NodeUniform;
+@group(2) @binding(1) var<storage, read> joint_matrices: array<mat4x4<f32>>;
+@group(2) @binding(2) var<storage, read> morph_deltas: array<MorphDelta>;
+@group(3) @binding(0) var<uniform> light: LightUniform;
 
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    let world_pos = node_matrix * vec4<f32>(in.position, 1.0);
+    var position = in.position;
+    var normal = in.normal;
+    for (var i = 0u; i < node.morph_target_count; i++) {
+        let weight = node.morph_weights[i / 4u][i % 4u];
+        let delta = morph_deltas[in.morph_index + i];
+        position += weight * delta.position.xyz;
+        normal += weight * delta.normal.xyz;
+    }
+
+    var skin = mat4x4<f32>(vec4(1.0, 0.0, 0.0, 0.0), vec4(0.0, 1.0, 0.0, 0.0), vec4(0.0, 0.0, 1.0, 0.0), vec4(0.0, 0.0, 0.0, 1.0));
+    if (node.joint_count > 0u) {
+        skin = in.weights.x * joint_matrices[node.joint_offset + in.joints.x] +
+               in.weights.y * joint_matrices[node.joint_offset + in.joints.y] +
+               in.weights.z * joint_matrices[node.joint_offset + in.joints.z] +
+               in.weights.w * joint_matrices[node.joint_offset + in.joints.w];
+    }
+    let world_pos = node.transform * skin * vec4<f32>(position, 1.0);
     out.clip_pos = camera.view_proj * world_pos;
-    out.normal = in.normal;
+    out.normal = normal;
     out.color = in.color;
 	out.uv = in.uv;
 
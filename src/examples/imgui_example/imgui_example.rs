@@ -1,23 +1,23 @@
 use std::{iter, time::Duration, u32, num::NonZeroU32};
+use std::sync::Arc;
 use image::GenericImageView;
-use imgui::Context;
-use wgpu::{PrimitiveState, Face, MultisampleState, FragmentState, ColorTargetState, TextureFormat, Queue, ShaderModule, ShaderModuleDescriptor, ShaderStages, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindGroupEntry, util::DeviceExt, BindGroupDescriptor, BindGroup, Buffer, VertexState, Device, BindGroupLayout, Surface, Texture};
-use crate::{app::App, app::ShaderType, assets_helper::ResourceManager, input_event::{InputEvent, EventType}};
+use wgpu::{PrimitiveState, Face, MultisampleState, FragmentState, ColorTargetState, TextureFormat, Queue, ShaderModule, ShaderModuleDescriptor, ShaderStages, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindGroupEntry, util::DeviceExt, BindGroupDescriptor, BindGroup, Buffer, VertexState, Device, BindGroupLayout, Texture};
+use crate::{app::App, app::ShaderType, assets_helper::ResourceManager, imgui_context::{self, ImguiContext}, input_event::InputEvent};
+use crate::render_target::RenderTarget;
 
 pub struct Renderer {
     pipeline: wgpu::RenderPipeline,
     checker_bindgroup: BindGroup,
     cur_mip_bindgroup: BindGroup,
     cur_mip_buffer: Buffer,
-    queue: Queue,
+    queue: Arc<Queue>,
 
-    imgui_context: Context,
+    imgui_context: ImguiContext,
     imgui_renderer: imgui_wgpu::Renderer,
 }
 
 pub struct ImGUIExample {
     renderer : Renderer,
-    resolution : Option<[u32; 2]>,
     selected_mip_level: usize,
     dropdown_items: Vec<String>,
 }
@@ -26,15 +26,16 @@ impl<T: ResourceManager> App<T> for ImGUIExample{
     fn new(
         sc: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
-        queue: Queue,
+        queue: Arc<Queue>,
         shader_type: ShaderType,
         _: &T
     ) -> Self {
-        let mut imgui_context = imgui::Context::create();
-        imgui_context.io_mut().display_size = [sc.width as f32, sc.height as f32];
-        let imgui_renderer = imgui_wgpu::Renderer::new(&mut imgui_context, &device, &queue, imgui_wgpu::RendererConfig{
-            texture_format: sc.format,
-            ..Default::default()
+        let (imgui_context, imgui_renderer) = ImguiContext::new(|imgui_context| {
+            imgui_context.io_mut().display_size = [sc.width as f32, sc.height as f32];
+            imgui_wgpu::Renderer::new(imgui_context, &device, &queue, imgui_wgpu::RendererConfig{
+                texture_format: sc.format,
+                ..Default::default()
+            })
         });
         
         let checker_tex_bgl = device.create_bind_group_layout(
@@ -122,35 +123,19 @@ impl<T: ResourceManager> App<T> for ImGUIExample{
             imgui_renderer,
         };
 
-        Self{renderer, resolution: None, selected_mip_level: 0, dropdown_items}
-    }
-
-    fn resize(&mut self, sc: &wgpu::SurfaceConfiguration, _device: &wgpu::Device) {
-        self.resolution = Some([sc.width, sc.height]);
+        Self{renderer, selected_mip_level: 0, dropdown_items}
     }
 
     fn tick(&mut self, delta: f32) {
-        self.renderer.imgui_context.io_mut().update_delta_time(Duration::from_secs_f32(delta));
+        self.renderer.imgui_context.with(|imgui_context| imgui_context.io_mut().update_delta_time(Duration::from_secs_f32(delta)));
     }
 
     fn process_input(&mut self, event: &InputEvent) -> bool {
-        match event.event_type {
-            EventType::Move => self.renderer.imgui_context.io_mut().mouse_pos = [event.coords[0] as f32, event.coords[1] as f32],
-            EventType::Start => {
-                self.renderer.imgui_context.io_mut().mouse_down[0 as usize] = true;
-            },
-            EventType::End => self.renderer.imgui_context.io_mut().mouse_down[0 as usize] = false,
-            EventType::None => (),
-        };
+        self.renderer.imgui_context.with(|imgui_context| imgui_context::handle_input(imgui_context.io_mut(), event));
         false
     }
 
-    fn render(&mut self, surface: &wgpu::Surface, device: &wgpu::Device) -> Result<(), wgpu::SurfaceError> {
-        let output = surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
+    fn render(&mut self, target: &RenderTarget, device: &wgpu::Device, _alpha: f32) {
         let mut encoder = device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
@@ -163,7 +148,7 @@ impl<T: ResourceManager> App<T> for ImGUIExample{
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: &target.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -177,38 +162,46 @@ impl<T: ResourceManager> App<T> for ImGUIExample{
                 })],
                 depth_stencil_attachment: None
             });
+            target.apply_viewport(&mut render_pass);
         
             render_pass.set_pipeline(&self.renderer.pipeline);
             render_pass.set_bind_group(0, &self.renderer.checker_bindgroup, &[]);
             render_pass.set_bind_group(1, &self.renderer.cur_mip_bindgroup, &[]);
             render_pass.draw(0..3, 0..1);
         
-            let ui = self.renderer.imgui_context.frame();
-            ui.window("Settings")
-                .size([100.0, 50.0], imgui::Condition::FirstUseEver)
-                .build(|| {
-                    if let Some(_) = ui.begin_combo("Mip level", self.dropdown_items[self.selected_mip_level].as_str()) {
-                        for (index, val) in self.dropdown_items.iter().enumerate() {
-                            if self.selected_mip_level == index {
-                                ui.set_item_default_focus();
-                            }
-                            let clicked = ui.selectable_config(val)
-                                .selected(self.selected_mip_level == index)
-                                .build();
-                            if clicked {
-                                self.selected_mip_level = index;
+            let size = target.size();
+            self.renderer.imgui_context.with(|imgui_context| {
+                imgui_context.io_mut().display_size = [size[0] as f32, size[1] as f32];
+                let ui = imgui_context.frame();
+                ui.window("Settings")
+                    .size([100.0, 50.0], imgui::Condition::FirstUseEver)
+                    .build(|| {
+                        if let Some(_) = ui.begin_combo("Mip level", self.dropdown_items[self.selected_mip_level].as_str()) {
+                            for (index, val) in self.dropdown_items.iter().enumerate() {
+                                if self.selected_mip_level == index {
+                                    ui.set_item_default_focus();
+                                }
+                                let clicked = ui.selectable_config(val)
+                                    .selected(self.selected_mip_level == index)
+                                    .build();
+                                if clicked {
+                                    self.selected_mip_level = index;
+                                }
                             }
                         }
-                    }
-                });
-            let draw_data = self.renderer.imgui_context.render();
-            self.renderer.imgui_renderer.render(draw_data, &self.renderer.queue, device, &mut render_pass).unwrap();
+                    });
+                if let Some(profiler) = &target.profiler {
+                    profiler.draw_imgui(ui);
+                }
+                let draw_data = imgui_context.render();
+                // imgui skips drawing new windows on their first frame and hands out an empty (null) list
+                if draw_data.draw_lists_count() > 0 {
+                    self.renderer.imgui_renderer.render(draw_data, &self.renderer.queue, device, &mut render_pass).unwrap();
+                }
+            });
         }
         
         self.renderer.queue.submit(iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
     }
 }
 
@@ -1,27 +1,27 @@
 use std::{iter, mem};
+use std::sync::Arc;
 use std::f32::consts::PI;
 use std::time::Duration;
 use image::GenericImageView;
-use imgui::Context;
-use wgpu::{Queue, TextureFormat, VertexBufferLayout, VertexAttribute, ColorTargetState, VertexState, FragmentState, ShaderModule, PrimitiveState, Face, DepthStencilState, StencilState, DepthBiasState, MultisampleState, ShaderModuleDescriptor, RenderPipeline, RenderPassDepthStencilAttachment, Operations, TextureView, BindGroup, Buffer, BindGroupLayout, BindingResource, Device, Sampler, include_spirv_raw, Features};
+use wgpu::{Queue, TextureFormat, VertexBufferLayout, VertexAttribute, ColorTargetState, VertexState, FragmentState, ShaderModule, PrimitiveState, Face, DepthStencilState, StencilState, DepthBiasState, MultisampleState, ShaderModuleDescriptor, RenderPassDepthStencilAttachment, Operations, TextureView, BindGroup, Buffer, BindGroupLayout, BindingResource, Device, Sampler, include_spirv_raw, Features, PrimitiveTopology};
 use wgpu::Face::Back;
-use crate::{app::{App, ShaderType}, camera::{ArcballCamera, Camera}, model::{GLTFModel, Drawable, NOD_MM_BGL, MATERIAL_BGL, parse_gltf}, assets_helper::ResourceManager, input_event::InputEvent, skybox::{Skybox, DrawableSkybox}};
+use crate::{app::{App, ShaderType}, camera::{ArcballCamera, Camera, CAMERA_BGL}, model::{GLTFModel, Drawable, ModelPipelines, NOD_MM_BGL, MATERIAL_BGL, parse_gltf}, assets_helper::ResourceManager, imgui_context::{self, ImguiContext}, input_event::InputEvent, input_state::InputState, skybox::{Skybox, DrawableSkybox}};
+use crate::render_target::RenderTarget;
 use crate::app::AppVariant;
-use crate::input_event::EventType;
 
 const DEBUG_TEX_ITEMS: [&str; 7] = ["none", "base color", "normal", "occlusion", "emissive", "metallic", "roughness"];
 const DEBUG_ITEMS: [&str; 6] = ["None", "diff(l, n)", "F(l,h)", "G(l,v,h)", "D(h)", "Specular"];
 
 struct Renderer {
-    queue: Queue,
+    queue: Arc<Queue>,
     
-    pipeline: RenderPipeline,
+    pipelines: ModelPipelines,
     depth_tex_view: TextureView,
 
     light_buffer: Buffer,
     light_bind_group: BindGroup,
 
-    imgui_context: Context,
+    imgui_context: ImguiContext,
     imgui_renderer: imgui_wgpu::Renderer,
 }
 
@@ -41,37 +41,26 @@ impl<T: ResourceManager> App<T> for PBRExample {
     fn new(
         sc: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
-        queue: wgpu::Queue,
+        queue: Arc<Queue>,
         shader_type: ShaderType,
         resource_manager: &T
     ) -> Self {
-        let mut imgui_context = imgui::Context::create();
-        imgui_context.io_mut().display_size = [sc.width as f32, sc.height as f32];
-        let imgui_renderer = imgui_wgpu::Renderer::new(&mut imgui_context, &device, &queue, imgui_wgpu::RendererConfig{
-            texture_format: sc.format,
-            depth_format: Some(TextureFormat::Depth24Plus),
-            ..Default::default()
+        let (imgui_context, imgui_renderer) = ImguiContext::new(|imgui_context| {
+            imgui_context.io_mut().display_size = [sc.width as f32, sc.height as f32];
+            imgui_wgpu::Renderer::new(imgui_context, &device, &queue, imgui_wgpu::RendererConfig{
+                texture_format: sc.format,
+                depth_format: Some(TextureFormat::Depth24Plus),
+                ..Default::default()
+            })
         });
 
         //let model = pollster::block_on(parse_gltf("models/DamagedHelmet/glTF-Embedded/DamagedHelmet.gltf", &device, &queue, resource_manager));
-        let model = pollster::block_on(parse_gltf("./models/maserati_ghibli_hybrid/scene.gltf", &device, &queue, resource_manager));
+        let model = pollster::block_on(parse_gltf("./models/maserati_ghibli_hybrid/scene.gltf", &device, &queue, resource_manager)).unwrap_or_else(|error| {
+            println!("Failed to load the model - {:?}", error);
+            GLTFModel::empty(device)
+        });
         //let model = pollster::block_on(parse_gltf("./models/vehicle_zis-101/scene.gltf", &device, &queue, resource_manager));
-        let camera_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("camera_bind_group_layout"),
-            });
+        let camera_bind_group_layout = device.create_bind_group_layout(&CAMERA_BGL);
         let skybox = Skybox::default_ktx(device, &queue, sc.format, shader_type, &camera_bind_group_layout);
         let (light_bind_group_layout, light_bind_group, light_buffer) = {
             let light_uniform_size = mem::size_of::<LightData>() as wgpu::BufferAddress;
@@ -186,58 +175,66 @@ impl<T: ResourceManager> App<T> for PBRExample {
         };
 
         //let pipeline = Self::create_pbr_pipeline(&device, sc.format, &light_bind_group_layout, &camera_bind_group_layout, shader_type);
-        let pipeline = Self::create_pbr_pipeline(&device, sc.format, &light_bind_group_layout, &camera_bind_group_layout, ShaderType::SPIRV);
+        let pipelines = ModelPipelines::new(&model, |topology| Self::create_pbr_pipeline(&device, sc.format, &light_bind_group_layout, &camera_bind_group_layout, topology, shader_type));
         let depth_tex_view = Self::create_depth_texture(sc, device);
-        let renderer = Renderer { queue, pipeline, depth_tex_view, light_bind_group, light_buffer, imgui_context, imgui_renderer };
+        let renderer = Renderer { queue, pipelines, depth_tex_view, light_bind_group, light_buffer, imgui_context, imgui_renderer };
         let mut camera = ArcballCamera::new(&device, sc.width as f32, sc.height as f32, 45., 0.01, 200., 7., 6.);
         camera.azimuth = PI / 4.;
         camera.polar = -PI / 4.;
         Self{ renderer, model, skybox, camera, time_in_flight: 0.0, debug_view_texture: 0, debug_view_item: 0 }
     }
 
-    fn get_extra_device_features(_app_variant: AppVariant) -> Features {
-        Features::SPIRV_SHADER_PASSTHROUGH
+    fn get_extra_device_features(app_variant: &AppVariant) -> Features {
+        match app_variant.shader_type {
+            ShaderType::WGSL => Features::empty(),
+            ShaderType::SPIRV => Features::SPIRV_SHADER_PASSTHROUGH,
+        }
+    }
+
+    fn process_input_state(&mut self, input: &InputState) {
+        if input.was_released("frame") {
+            self.camera.frame_bounds(&self.model.bounds());
+        }
+        self.camera.input(input);
+    }
+
+    fn camera(&mut self) -> Option<&mut dyn Camera> {
+        Some(&mut self.camera)
     }
 
     fn process_input(&mut self, event: &InputEvent) -> bool {
-        self.camera.input(event);
-        match event.event_type {
-            EventType::Move => self.renderer.imgui_context.io_mut().mouse_pos = [event.coords[0] as f32, event.coords[1] as f32],
-            EventType::Start => {
-                self.renderer.imgui_context.io_mut().mouse_down[0 as usize] = true;
-            },
-            EventType::End => self.renderer.imgui_context.io_mut().mouse_down[0 as usize] = false,
-            EventType::None => (),
-        };
+        self.renderer.imgui_context.with(|imgui_context| imgui_context::handle_input(imgui_context.io_mut(), event));
         false
     }
 
     fn tick(&mut self, delta: f32) {
         self.camera.tick(delta, &self.renderer.queue);
-        self.renderer.imgui_context.io_mut().update_delta_time(Duration::from_secs_f32(delta));
+        self.renderer.imgui_context.with(|imgui_context| imgui_context.io_mut().update_delta_time(Duration::from_secs_f32(delta)));
+        self.model.update(&self.renderer.queue, delta);
         self.time_in_flight += delta;
     }
 
-    fn render(&mut self, surface: &wgpu::Surface, device: &wgpu::Device) -> Result<(), wgpu::SurfaceError> {
-        self.camera.tick(0.01, &self.renderer.queue);
+    fn render(&mut self, target: &RenderTarget, device: &wgpu::Device, _alpha: f32) {
         let light_data = Self::get_light_matrix(self.time_in_flight);
         self.renderer.queue.write_buffer(&self.renderer.light_buffer, 0, bytemuck::cast_slice(&[light_data]));
 
-        let output = surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
         let mut encoder = device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
+        let main_scope = target.begin_gpu_scope(&mut encoder, "Main pass");
+        // edited by the ui while the model is borrowed by the pass
+        let mut playback = self.model.playback();
+        let current_morph_weights: Vec<(usize, Vec<f32>)> = self.model.morph_weights().map(|(node, weights)| (node, weights.to_vec())).collect();
+        let mut morph_weights = current_morph_weights.clone();
+        let mut scene = self.model.scene();
+        let mut frame_node = None;
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: &target.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -258,62 +255,144 @@ impl<T: ResourceManager> App<T> for PBRExample {
                     stencil_ops: None,
                 })
             });
+            target.apply_viewport(&mut render_pass);
 
-            render_pass.set_pipeline(&self.renderer.pipeline);
-            render_pass.set_bind_group(0, &self.camera.camera_bind_group, &[]);
+            render_pass.set_bind_group(0, self.camera.bind_group(), &[]);
             render_pass.set_bind_group(3, &self.renderer.light_bind_group, &[]);
-            render_pass.draw_model(&self.model, 2);
-
-            //render_pass.draw_skybox(&self.skybox, &self.camera.camera_bind_group);
-
-            let ui = self.renderer.imgui_context.frame();
-            ui.window("Settings")
-                .size([100.0, 50.0], imgui::Condition::FirstUseEver)
-                .build(|| {
-                    if let Some(_) = ui.begin_combo("Debug texture", DEBUG_TEX_ITEMS[self.debug_view_texture]) {
-                        for (index, val) in DEBUG_TEX_ITEMS.iter().enumerate() {
-                            if self.debug_view_texture == index {
-                                ui.set_item_default_focus();
+            let stats = render_pass.draw_model(&self.model, &self.renderer.pipelines, 2, &self.camera.frustum());
+            target.count("primitives visible", stats.visible);
+            target.count("primitives culled", stats.culled);
+
+            //render_pass.draw_skybox(&self.skybox, self.camera.bind_group());
+
+            let size = target.size();
+            self.renderer.imgui_context.with(|imgui_context| {
+                imgui_context.io_mut().display_size = [size[0] as f32, size[1] as f32];
+                let ui = imgui_context.frame();
+                ui.window("Settings")
+                    .size([100.0, 50.0], imgui::Condition::FirstUseEver)
+                    .build(|| {
+                        if let Some(_) = ui.begin_combo("Debug texture", DEBUG_TEX_ITEMS[self.debug_view_texture]) {
+                            for (index, val) in DEBUG_TEX_ITEMS.iter().enumerate() {
+                                if self.debug_view_texture == index {
+                                    ui.set_item_default_focus();
+                                }
+                                let clicked = ui.selectable_config(val)
+                                    .selected(self.debug_view_texture == index)
+                                    .build();
+                                if clicked {
+                                    self.debug_view_texture = index;
+                                }
                             }
-                            let clicked = ui.selectable_config(val)
-                                .selected(self.debug_view_texture == index)
-                                .build();
-                            if clicked {
-                                self.debug_view_texture = index;
+                        }
+                        if let Some(_) = ui.begin_combo("Debug view", DEBUG_ITEMS[self.debug_view_item]) {
+                            for (index, val) in DEBUG_ITEMS.iter().enumerate() {
+                                if self.debug_view_item == index {
+                                    ui.set_item_default_focus();
+                                }
+                                let clicked = ui.selectable_config(val)
+                                    .selected(self.debug_view_item == index)
+                                    .build();
+                                if clicked {
+                                    self.debug_view_item = index;
+                                }
                             }
                         }
-                    }
-                    if let Some(_) = ui.begin_combo("Debug view", DEBUG_ITEMS[self.debug_view_item]) {
-                        for (index, val) in DEBUG_ITEMS.iter().enumerate() {
-                            if self.debug_view_item == index {
-                                ui.set_item_default_focus();
+                        ui.text(format!("{} primitives drawn, {} culled", stats.visible, stats.culled));
+                        let scenes = self.model.scenes();
+                        if scenes.len() > 1 {
+                            let name = |index: usize| scenes[index].name.clone().unwrap_or_else(|| format!("Scene {}", index));
+                            if let Some(_combo) = ui.begin_combo("Scene", name(scene)) {
+                                for index in 0..scenes.len() {
+                                    if ui.selectable_config(name(index)).selected(scene == index).build() {
+                                        scene = index;
+                                    }
+                                }
                             }
-                            let clicked = ui.selectable_config(val)
-                                .selected(self.debug_view_item == index)
-                                .build();
-                            if clicked {
-                                self.debug_view_item = index;
+                        }
+                        if let (Some(_tree), Some(scene)) = (ui.tree_node("Nodes"), scenes.get(self.model.scene())) {
+                            for root in scene.roots() {
+                                Self::node_tree(ui, &self.model, *root, &mut frame_node);
                             }
                         }
-                    }
-                });
-            let draw_data = self.renderer.imgui_context.render();
-            self.renderer.imgui_renderer.render(draw_data, &self.renderer.queue, device, &mut render_pass).unwrap();
+                        let animations = self.model.animations();
+                        if let Some(animation) = animations.get(playback.animation) {
+                            let name = |index: usize| animations[index].name.clone().unwrap_or_else(|| format!("Animation {}", index));
+                            if let Some(_combo) = ui.begin_combo("Animation", name(playback.animation)) {
+                                for index in 0..animations.len() {
+                                    if ui.selectable_config(name(index)).selected(playback.animation == index).build() {
+                                        playback.animation = index;
+                                    }
+                                }
+                            }
+                            ui.slider("Time", 0.0, animation.duration, &mut playback.time);
+                            ui.checkbox("Playing", &mut playback.playing);
+                            ui.checkbox("Loop", &mut playback.looping);
+                        }
+                        for (node, weights) in &mut morph_weights {
+                            for (i, weight) in weights.iter_mut().enumerate() {
+                                ui.slider(format!("Node {} morph target {}", node, i), 0.0, 1.0, weight);
+                            }
+                        }
+                    });
+                if let Some(profiler) = &target.profiler {
+                    profiler.draw_imgui(ui);
+                }
+                let draw_data = imgui_context.render();
+                // imgui skips drawing new windows on their first frame and hands out an empty (null) list
+                if draw_data.draw_lists_count() > 0 {
+                    self.renderer.imgui_renderer.render(draw_data, &self.renderer.queue, device, &mut render_pass).unwrap();
+                }
+            });
         }
+        target.end_gpu_scope(&mut encoder, main_scope);
 
-        self.renderer.queue.submit(iter::once(encoder.finish()));
-        output.present();
+        let current = self.model.playback();
+        if playback.animation != current.animation {
+            self.model.play(playback.animation);
+        } else if playback.time != current.time {
+            self.model.seek(playback.time);
+        }
+        self.model.set_playing(playback.playing);
+        self.model.set_looping(playback.looping);
+        for ((node, weights), (_, current)) in morph_weights.iter().zip(&current_morph_weights) {
+            if weights != current {
+                self.model.set_morph_weights(*node, weights);
+            }
+        }
+        if scene != self.model.scene() {
+            self.model.set_scene(scene);
+        }
+        if let Some(node) = frame_node {
+            self.camera.frame_bounds(&self.model.subtree_bounds(node));
+        }
 
-        Ok(())
+        self.renderer.queue.submit(iter::once(encoder.finish()));
     }
 }
 
 impl PBRExample {
-    fn create_pbr_pipeline(device: &wgpu::Device, tex_format: TextureFormat, light_bind_group_layout: &BindGroupLayout, camera_bind_group_layout: &BindGroupLayout, shader_type: ShaderType) -> wgpu::RenderPipeline {
+    // The node's name, world position and children, frame_node is set when its Frame button gets clicked
+    fn node_tree(ui: &imgui::Ui, model: &GLTFModel, node: usize, frame_node: &mut Option<usize>) {
+        let name = model.nodes()[node].name().map_or_else(|| format!("Node {}", node), str::to_string);
+        if let Some(_tree) = ui.tree_node(format!("{}##{}", name, node)) {
+            if let Some(transform) = model.world_transform(node) {
+                ui.text(format!("at {:.2} {:.2} {:.2}", transform[(0, 3)], transform[(1, 3)], transform[(2, 3)]));
+            }
+            if ui.small_button(format!("Frame##{}", node)) {
+                *frame_node = Some(node);
+            }
+            for child in model.nodes()[node].children() {
+                Self::node_tree(ui, model, child, frame_node);
+            }
+        }
+    }
+
+    fn create_pbr_pipeline(device: &wgpu::Device, tex_format: TextureFormat, light_bind_group_layout: &BindGroupLayout, camera_bind_group_layout: &BindGroupLayout, topology: PrimitiveTopology, shader_type: ShaderType) -> wgpu::RenderPipeline {
         let buffer_layout = 
         [
             VertexBufferLayout{
-                array_stride: std::mem::size_of::<[f32; 13]>() as wgpu::BufferAddress,
+                array_stride: std::mem::size_of::<[f32; 26]>() as wgpu::BufferAddress,
                 step_mode: wgpu::VertexStepMode::Vertex,
                 attributes: &[VertexAttribute{
                     format: wgpu::VertexFormat::Float32x3,
@@ -339,6 +418,26 @@ impl PBRExample {
                     format: wgpu::VertexFormat::Float32x3,
                     offset: std::mem::size_of::<[f32; 10]>() as wgpu::BufferAddress,
                     shader_location: 4,
+                },
+                VertexAttribute{
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 13]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                },
+                VertexAttribute{
+                    format: wgpu::VertexFormat::Uint32x4,
+                    offset: std::mem::size_of::<[f32; 17]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                },
+                VertexAttribute{
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: std::mem::size_of::<[f32; 21]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                },
+                VertexAttribute{
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: std::mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
+                    shader_location: 8,
                 }]
             }
         ];
@@ -410,7 +509,7 @@ impl PBRExample {
             layout: Some(&pipeline_layout),
             vertex: vertex_state,
             primitive: PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
+                topology,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
                 cull_mode: None,
@@ -5,6 +5,7 @@ layout (location = 1) in vec3 in_normal;
 layout (location = 2) in vec2 in_uv0;
 layout (location = 3) in vec2 in_uv1;
 layout (location = 4) in vec3 in_color;
+layout (location = 5) in vec4 in_tangent;
 
 layout(set = 0, binding = 0) uniform CameraParams {
     mat4 projection;
@@ -135,6 +136,11 @@ vec3 getNormal()
     vec3 N = normalize(in_normal);
     vec3 T = normalize(q1 * st2.t - q2 * st1.t);
     vec3 B = -normalize(cross(N, T));
+    // the derivatives only stand in for missing tangents
+    if (dot(in_tangent.xyz, in_tangent.xyz) > 0.0) {
+        T = normalize(in_tangent.xyz);
+        B = cross(N, T) * in_tangent.w;
+    }
     mat3 TBN = mat3(T, B, N);
 
     return normalize(TBN * tangentNormal);
@@ -8,27 +8,66 @@ layout(set = 0, binding = 0) uniform CameraParams {
 } camera_params;
 layout(set = 2, binding = 0) uniform UBONode {
     mat4 transform;
-    //mat4 jointMatrix[64];
-    //float jointCount;
+    uint joint_count;
+    uint morph_target_count;
+    uint joint_offset;
+    vec4 morph_weights[2];
 } node;
+layout(std430, set = 2, binding = 1) readonly buffer JointMatrices {
+    mat4 joint_matrices[];
+};
+struct MorphDelta {
+    vec4 position;
+    vec4 normal;
+    vec4 tangent;
+};
+layout(std430, set = 2, binding = 2) readonly buffer MorphDeltas {
+    MorphDelta morph_deltas[];
+};
 
 layout (location = 0) in vec3 in_pos;
 layout (location = 1) in vec3 in_normal;
 layout (location = 2) in vec2 in_uv0;
 layout (location = 3) in vec2 in_uv1;
 layout (location = 4) in vec3 in_color;
+layout (location = 5) in vec4 in_tangent;
+layout (location = 6) in uvec4 in_joints;
+layout (location = 7) in vec4 in_weights;
+layout (location = 8) in uint in_morph_index;
 
 layout (location = 0) out vec3 out_world_pos;
 layout (location = 1) out vec3 out_normal;
 layout (location = 2) out vec2 out_uv0;
 layout (location = 3) out vec2 out_uv1;
 layout (location = 4) out vec3 out_color;
+layout (location = 5) out vec4 out_tangent;
 
 void main() {
     out_color = in_color;
 
-    vec4 loc_pos = camera_params.model * node.transform * vec4(in_pos, 1.0);
-    out_normal = normalize(transpose(inverse(mat3(camera_params.model * node.transform))) * in_normal);
+    vec3 pos = in_pos;
+    vec3 normal = in_normal;
+    vec3 tangent = in_tangent.xyz;
+    for (uint i = 0; i < node.morph_target_count; i++) {
+        float weight = node.morph_weights[i / 4][i % 4];
+        MorphDelta delta = morph_deltas[in_morph_index + i];
+        pos += weight * delta.position.xyz;
+        normal += weight * delta.normal.xyz;
+        tangent += weight * delta.tangent.xyz;
+    }
+
+    mat4 skin = mat4(1.0);
+    if (node.joint_count > 0) {
+        skin = in_weights.x * joint_matrices[node.joint_offset + in_joints.x] +
+               in_weights.y * joint_matrices[node.joint_offset + in_joints.y] +
+               in_weights.z * joint_matrices[node.joint_offset + in_joints.z] +
+               in_weights.w * joint_matrices[node.joint_offset + in_joints.w];
+    }
+    mat4 model = camera_params.model * node.transform * skin;
+
+    vec4 loc_pos = model * vec4(pos, 1.0);
+    out_normal = normalize(transpose(inverse(mat3(model))) * normal);
+    out_tangent = vec4(mat3(model) * tangent, in_tangent.w);
 
     //locPos.y = -locPos.y;
     out_world_pos = loc_pos.xyz / loc_pos.w;
@@ -8,25 +8,43 @@ struct VertexInput {
     @location(2) uv0 :              vec2<f32>,
     @location(3) uv1 :              vec2<f32>,
     @location(4) color :            vec3<f32>,
-    //location(4) inJoint0 :    vec4<u32>;
-    //location(5) inWeight0 :   vec4<f32>;
+    @location(5) tangent :          vec4<f32>,
+    @location(6) joints :           vec4<u32>,
+    @location(7) weights :          vec4<f32>,
+    @location(8) morph_index :      u32,
 };
 
-struct CameraParams {
-  projection :                      mat4x4<f32>,
-  model :                           mat4x4<f32>,
-  view :                            mat4x4<f32>,
-  position :                        vec4<f32>,
+struct CameraUniform {
+    proj:       mat4x4<f32>,
+    model:      mat4x4<f32>,
+    view:       mat4x4<f32>,
+    position:   vec4<f32>,
+    view_proj:  mat4x4<f32>,
+    inv_proj:   mat4x4<f32>,
+    inv_view:   mat4x4<f32>,
+    viewport:   vec4<f32>,
+    near:       f32,
+    far:        f32,
+    jitter:     vec2<f32>,
 };
-@group(0) @binding(0) var<uniform> camera_params : CameraParams;
+@group(0) @binding(0) var<uniform> camera_params : CameraUniform;
 //group(1) for pbr textures set
 
 struct UBONode {
   transform :                          mat4x4<f32>,
-  //jointMatrix : [[stride(64)]] array<mat4x4<f32>, MAX_NUM_JOINTS>;
-  //jointCount : f32;
+  joint_count :                        u32,
+  morph_target_count :                 u32,
+  joint_offset :                       u32,
+  morph_weights :                      array<vec4<f32>, 2>,
+};
+struct MorphDelta {
+  position :                           vec4<f32>,
+  normal :                             vec4<f32>,
+  tangent :                            vec4<f32>,
 };
 @group(2) @binding(0) var<uniform> node : UBONode;
+@group(2) @binding(1) var<storage, read> joint_matrices : array<mat4x4<f32>>;
+@group(2) @binding(2) var<storage, read> morph_deltas : array<MorphDelta>;
 
 struct LightingParams {
 	light_dir:                      vec4<f32>,
@@ -52,6 +70,7 @@ struct VertexOutput {
     @location(2) uv0: vec2<f32>,
     @location(3) uv1: vec2<f32>,
     @location(4) color: vec3<f32>,
+    @location(5) tangent: vec4<f32>,
 };
 
 @vertex
@@ -59,16 +78,36 @@ fn vs_main(in: VertexInput) -> VertexOutput {
     var out: VertexOutput;
     out.color = in.color;
 
-    let locPos = camera_params.model * node.transform * vec4(in.pos, 1.0);
+    var pos = in.pos;
+    var normal = in.normal;
+    var tangent = in.tangent.xyz;
+    for (var i = 0u; i < node.morph_target_count; i++) {
+        let weight = node.morph_weights[i / 4u][i % 4u];
+        let delta = morph_deltas[in.morph_index + i];
+        pos += weight * delta.position.xyz;
+        normal += weight * delta.normal.xyz;
+        tangent += weight * delta.tangent.xyz;
+    }
+
+    var skin = mat4x4<f32>(vec4(1.0, 0.0, 0.0, 0.0), vec4(0.0, 1.0, 0.0, 0.0), vec4(0.0, 0.0, 1.0, 0.0), vec4(0.0, 0.0, 0.0, 1.0));
+    if (node.joint_count > 0u) {
+        skin = in.weights.x * joint_matrices[node.joint_offset + in.joints.x] +
+               in.weights.y * joint_matrices[node.joint_offset + in.joints.y] +
+               in.weights.z * joint_matrices[node.joint_offset + in.joints.z] +
+               in.weights.w * joint_matrices[node.joint_offset + in.joints.w];
+    }
+    let model = camera_params.model * node.transform * skin;
+
+    let locPos = model * vec4(pos, 1.0);
     //out.normal = normalize(transpose(inverse(mat3(camera_params.model * node.matrix))) * in.normal);
-    out.normal = normalize(camera_params.model * node.transform * vec4(in.normal, 1.0)).xyz;
+    out.normal = normalize(model * vec4(normal, 1.0)).xyz;
+    out.tangent = vec4((model * vec4(tangent, 0.0)).xyz, in.tangent.w);
 
     //locPos.y = -locPos.y;
 	out.world_pos = locPos.xyz / locPos.w;
 	out.uv0 = in.uv0;
     out.uv1 = in.uv1;
-    out.clip_pos = camera_params.projection * camera_params.view * vec4(out.world_pos, 1.0);
-    //out.clip_pos = camera_params.view_proj * vec4(out.world_pos, 1.0);
+    out.clip_pos = camera_params.view_proj * vec4(out.world_pos, 1.0);
     
     return out;
 }
@@ -244,8 +283,13 @@ fn get_normal(in: VertexOutput) -> vec3<f32> {
     let st2 = dpdy(in.uv0);
 
     let N = normalize(in.normal);
-    let T = normalize(q1 * st2.y - q2 * st1.y);
-    let B = -normalize(cross(N, T));
+    var T = normalize(q1 * st2.y - q2 * st1.y);
+    var B = -normalize(cross(N, T));
+    // the derivatives only stand in for missing tangents
+    if (dot(in.tangent.xyz, in.tangent.xyz) > 0.0) {
+        T = normalize(in.tangent.xyz);
+        B = cross(N, T) * in.tangent.w;
+    }
     let TBN = mat3x3<f32>(T, B, N);
 
     return normalize(TBN * tangent_normal);
@@ -1,10 +1,12 @@
 use std::{iter, mem};
+use std::sync::Arc;
 
 use wgpu::{Queue, TextureFormat, VertexBufferLayout, VertexAttribute, ColorTargetState, VertexState, FragmentState, ShaderModule, PrimitiveState, Face, DepthStencilState, StencilState, DepthBiasState, MultisampleState, ShaderModuleDescriptor, RenderPipeline, RenderPassDepthStencilAttachment, Operations, TextureView, RenderPipelineDescriptor, Sampler, BindGroup, Buffer, BindGroupLayout, BindGroupDescriptor, BindGroupLayoutDescriptor, BindingType, BindGroupEntry};
 
-use crate::{app::{App, ShaderType}, assets_helper::{Mesh, ResourceManager}, camera::{ArcballCamera, Camera}, input_event::InputEvent};
+use crate::{app::{App, ShaderType}, assets_helper::{Mesh, ResourceManager}, camera::{ArcballCamera, Camera, CAMERA_BGL}, input_state::InputState};
+use crate::render_target::RenderTarget;
 struct Renderer {
-    queue: Queue,
+    queue: Arc<Queue>,
     
     pipeline: RenderPipeline,
     depth_tex_view: TextureView,
@@ -33,7 +35,7 @@ impl<T: ResourceManager> App<T> for ShadowMappingExample {
     fn new(
         sc: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
-        queue: wgpu::Queue,
+        queue: Arc<Queue>,
         shader_type: ShaderType,
         resource_manager: &T
     ) -> Self {
@@ -85,20 +87,16 @@ impl<T: ResourceManager> App<T> for ShadowMappingExample {
         Self{ renderer, meshes, camera, time_in_flight: 0.0 }
     }
 
-    fn render(&mut self, surface: &wgpu::Surface, device: &wgpu::Device) -> Result<(), wgpu::SurfaceError> {
+    fn render(&mut self, target: &RenderTarget, device: &wgpu::Device, _alpha: f32) {
         let light_data = Self::get_light_matrix(self.time_in_flight);
         self.renderer.queue.write_buffer(&self.renderer.light_buffer, 0, bytemuck::cast_slice(&[light_data]));
         
-        let output = surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
         let mut encoder = device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
         encoder.insert_debug_marker("shadow pass");
+        let shadow_scope = target.begin_gpu_scope(&mut encoder, "Shadow pass");
         {
             let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Shadow pass"),
@@ -121,13 +119,15 @@ impl<T: ResourceManager> App<T> for ShadowMappingExample {
                 pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
             }
         }
+        target.end_gpu_scope(&mut encoder, shadow_scope);
         encoder.pop_debug_group();
 
+        let main_scope = target.begin_gpu_scope(&mut encoder, "Main pass");
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: &target.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -148,9 +148,10 @@ impl<T: ResourceManager> App<T> for ShadowMappingExample {
                     stencil_ops: None,
                 })
             });
+            target.apply_viewport(&mut render_pass);
         
             render_pass.set_pipeline(&self.renderer.pipeline);
-            render_pass.set_bind_group(0, &self.camera.camera_bind_group, &[]);
+            render_pass.set_bind_group(0, self.camera.bind_group(), &[]);
             render_pass.set_bind_group(1, &self.renderer.light_bind_group, &[]);
             render_pass.set_bind_group(2, &self.renderer.shadow_tex_bind_group, &[]);
             for mesh in &self.meshes {
@@ -159,16 +160,17 @@ impl<T: ResourceManager> App<T> for ShadowMappingExample {
                 render_pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
             }
         }
+        target.end_gpu_scope(&mut encoder, main_scope);
         
         self.renderer.queue.submit(iter::once(encoder.finish()));
-        output.present();
+    }
 
-        Ok(())
+    fn process_input_state(&mut self, input: &InputState) {
+        self.camera.input(input);
     }
 
-    fn process_input(&mut self, event: &InputEvent) -> bool {
-        self.camera.input(event);
-        false
+    fn camera(&mut self) -> Option<&mut dyn Camera> {
+        Some(&mut self.camera)
     }
 
     fn tick(&mut self, delta: f32) {
@@ -235,20 +237,7 @@ impl ShadowMappingExample {
             _ => panic!()
         }
         
-        let camera_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            label: Some("camera_bind_group_layout"),
-        });
+        let camera_bind_group_layout = device.create_bind_group_layout(&CAMERA_BGL);
         let shadow_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor{
             label: Some("shadow bind group layout"),
             entries: &[wgpu::BindGroupLayoutEntry {
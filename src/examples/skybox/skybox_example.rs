@@ -1,9 +1,14 @@
 use std::iter;
+use std::sync::Arc;
 use wgpu::Queue;
-use crate::{app::App, app::ShaderType, assets_helper::ResourceManager, camera::{ArcballCamera, Camera}, input_event::InputEvent, skybox::{Skybox, Drawable}};
+use crate::{app::App, app::ShaderType, assets_helper::ResourceManager, camera::{ArcballCamera, Camera, CAMERA_BGL}, input_state::InputState, skybox::{Skybox, DrawableSkybox}};
+use crate::render_target::RenderTarget;
+
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24Plus;
 
 pub struct Renderer {
-    queue: Queue,
+    queue: Arc<Queue>,
+    depth_tex_view: wgpu::TextureView,
 }
 
 pub struct SkyboxExample {
@@ -16,39 +21,22 @@ impl<T: ResourceManager> App<T> for SkyboxExample{
     fn new(
         sc: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
-        queue: Queue,
+        queue: Arc<Queue>,
         shader_type: ShaderType,
         resource_manager: &T
     ) -> Self {
         let camera = ArcballCamera::new(&device, sc.width as f32, sc.height as f32, 45., 0.01, 100., 7., 35.);
-        let camera_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            label: Some("camera_bind_group_layout"),
-        });
-        let skybox = Skybox::new(device, &queue, resource_manager, sc.format, shader_type, &camera_bind_group_layout);
+        let camera_bind_group_layout = device.create_bind_group_layout(&CAMERA_BGL);
+        let skybox = Skybox::new(device, &queue, resource_manager, sc.format, shader_type, &camera_bind_group_layout, true);
+        let depth_tex_view = Self::create_depth_texture(sc, device);
         Self{
-            renderer: Renderer { queue },
+            renderer: Renderer { queue, depth_tex_view },
             skybox: skybox,
             camera
         }
     }
 
-    fn render(&mut self, surface: &wgpu::Surface, device: &wgpu::Device) -> Result<(), wgpu::SurfaceError> {
-        let output = surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
+    fn render(&mut self, target: &RenderTarget, device: &wgpu::Device, _alpha: f32) {
         let mut encoder = device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
@@ -58,7 +46,7 @@ impl<T: ResourceManager> App<T> for SkyboxExample{
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: &target.view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -70,24 +58,60 @@ impl<T: ResourceManager> App<T> for SkyboxExample{
                         store: true,
                     },
                 })],
-                depth_stencil_attachment: None
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.renderer.depth_tex_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
             });
+            target.apply_viewport(&mut render_pass);
             
-            render_pass.draw_skybox(&self.skybox, &self.camera.camera_bind_group);
+            render_pass.draw_skybox(&self.skybox, self.camera.bind_group());
         }
         
         self.renderer.queue.submit(iter::once(encoder.finish()));
-        output.present();
+    }
+
+    fn process_input_state(&mut self, input: &InputState) {
+        self.camera.input(input);
+    }
 
-        Ok(())
+    fn camera(&mut self) -> Option<&mut dyn Camera> {
+        Some(&mut self.camera)
     }
 
-    fn process_input(&mut self, event: &InputEvent) -> bool {
-        self.camera.input(event);
-        false
+    fn resize(&mut self, sc: &wgpu::SurfaceConfiguration, device: &wgpu::Device) {
+        self.renderer.depth_tex_view = Self::create_depth_texture(sc, device);
     }
 
     fn tick(&mut self, delta: f32) {
         self.camera.tick(delta, &self.renderer.queue)
     }
+}
+
+impl SkyboxExample {
+    fn create_depth_texture(
+        config: &wgpu::SurfaceConfiguration,
+        device: &wgpu::Device,
+    ) -> wgpu::TextureView {
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            label: None,
+            view_formats: &[],
+        });
+
+        depth_texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
 }
\ No newline at end of file
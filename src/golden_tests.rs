@@ -0,0 +1,183 @@
+// Golden image regression tests. Every example is rendered headlessly on a software adapter with
+// a fixed resolution and fixed tick deltas, read back and compared against tests/golden/<name>.png.
+//
+// UPDATE_GOLDEN=1 cargo test golden   - (re)writes the reference images
+// A missing reference image fails the test. On mismatch the actual frame and a diff image are
+// written to target/golden_failures/. Examples needing features software adapters lack, or assets
+// that aren't checked in, are #[ignore]d.
+
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+use crate::{app::{App, AppVariant, ShaderType}, assets_helper::DesktopResourceManager, headless_app::HeadlessRunner};
+use crate::{boxes::BoxesExample, flipboard::FlipboardExample, fullscreen_triangle::FullscreenTriangleExample, gltf_viewer::GLTFViewerExample,
+            imgui_example::ImGUIExample, pbr::PBRExample, shadow_mapping::ShadowMappingExample, skybox_example::SkyboxExample};
+
+const GOLDEN_WIDTH: u32 = 320;
+const GOLDEN_HEIGHT: u32 = 240;
+const GOLDEN_FRAMES: u32 = 3;
+const GOLDEN_FRAME_DELTA: f32 = 1.0 / 60.0;
+
+// Per pixel YIQ distance threshold in [0, 1], same metric as pixelmatch uses
+const PIXEL_THRESHOLD: f32 = 0.1;
+// Fraction of pixels that are allowed to exceed PIXEL_THRESHOLD
+const MAX_DIFF_RATIO: f32 = 0.005;
+// Maximum possible YIQ delta between two colors
+const MAX_YIQ_DELTA: f32 = 35215.0;
+
+struct Comparison {
+    diff_pixels: u32,
+    diff_image: RgbaImage,
+}
+
+fn golden_variant() -> AppVariant {
+    AppVariant {
+        shader_type: ShaderType::WGSL,
+        headless: true,
+        frames: GOLDEN_FRAMES,
+        frame_delta: GOLDEN_FRAME_DELTA,
+        width: GOLDEN_WIDTH,
+        height: GOLDEN_HEIGHT,
+        output_dir: failures_dir(),
+        software: true,
+        ..AppVariant::default()
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.png", name))
+}
+
+fn failures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("golden_failures")
+}
+
+fn yiq_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    // blend with white background so that transparent pixels compare sanely
+    let blend = |pixel: &Rgba<u8>| {
+        let alpha = pixel[3] as f32 / 255.0;
+        [0, 1, 2].map(|c| 255.0 + (pixel[c] as f32 - 255.0) * alpha)
+    };
+    let (a, b) = (blend(a), blend(b));
+    let y = |c: &[f32; 3]| c[0] * 0.29889531 + c[1] * 0.58662247 + c[2] * 0.11448223;
+    let i = |c: &[f32; 3]| c[0] * 0.59597799 - c[1] * 0.27417610 - c[2] * 0.32180189;
+    let q = |c: &[f32; 3]| c[0] * 0.21147017 - c[1] * 0.52261711 + c[2] * 0.31114694;
+    let (dy, di, dq) = (y(&a) - y(&b), i(&a) - i(&b), q(&a) - q(&b));
+    0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq
+}
+
+fn compare_images(actual: &RgbaImage, expected: &RgbaImage) -> Comparison {
+    let max_delta = MAX_YIQ_DELTA * PIXEL_THRESHOLD * PIXEL_THRESHOLD;
+    let mut diff_pixels = 0;
+    let mut diff_image = RgbaImage::new(actual.width(), actual.height());
+    for (x, y, pixel) in actual.enumerate_pixels() {
+        let expected_pixel = expected.get_pixel(x, y);
+        let out = if yiq_delta(pixel, expected_pixel) > max_delta {
+            diff_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // faded grayscale of the expected image to give the red pixels some context
+            let gray = (expected_pixel[0] as u32 * 77 + expected_pixel[1] as u32 * 150 + expected_pixel[2] as u32 * 29) >> 8;
+            let faded = (255 - (255 - gray) / 10) as u8;
+            Rgba([faded, faded, faded, 255])
+        };
+        diff_image.put_pixel(x, y, out);
+    }
+    Comparison { diff_pixels, diff_image }
+}
+
+fn check_golden<T: App<DesktopResourceManager>>(name: &str) {
+    let app_variant = golden_variant();
+    let mut runner = pollster::block_on(HeadlessRunner::<T>::new(&app_variant))
+        .unwrap_or_else(|error| panic!("{}: no adapter/device with the required features: {:?}", name, error));
+    let mut actual = None;
+    for _ in 0..app_variant.frames {
+        actual = Some(runner.render_frame(app_variant.frame_delta).unwrap());
+    }
+    let actual = actual.unwrap();
+
+    let path = golden_path(name);
+    if std::env::var("UPDATE_GOLDEN").is_ok() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        actual.save(&path).unwrap();
+        eprintln!("Golden image written to {}", path.display());
+        return;
+    }
+
+    let expected = image::open(&path)
+        .unwrap_or_else(|error| panic!("{}: can't open golden image {} ({}), UPDATE_GOLDEN=1 writes it", name, path.display(), error))
+        .to_rgba8();
+    assert_eq!(expected.dimensions(), actual.dimensions(), "{}: golden image has a different size", name);
+
+    let comparison = compare_images(&actual, &expected);
+    let diff_ratio = comparison.diff_pixels as f32 / (actual.width() * actual.height()) as f32;
+    if diff_ratio > MAX_DIFF_RATIO {
+        let dir = failures_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        actual.save(dir.join(format!("{}_actual.png", name))).unwrap();
+        comparison.diff_image.save(dir.join(format!("{}_diff.png", name))).unwrap();
+        panic!("{}: {} pixels ({:.2}%) differ from {}, see {}",
+               name, comparison.diff_pixels, diff_ratio * 100.0, path.display(), dir.display());
+    }
+}
+
+#[test]
+fn compare_identical_images() {
+    let image = RgbaImage::from_fn(16, 16, |x, y| Rgba([(x * 16) as u8, (y * 16) as u8, 128, 255]));
+    assert_eq!(compare_images(&image, &image).diff_pixels, 0);
+}
+
+#[test]
+fn compare_detects_changed_pixels() {
+    let expected = RgbaImage::from_pixel(16, 16, Rgba([20, 20, 20, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(3, 4, Rgba([250, 20, 20, 255]));
+    // barely visible noise has to stay under the threshold
+    actual.put_pixel(5, 5, Rgba([22, 21, 20, 255]));
+    let comparison = compare_images(&actual, &expected);
+    assert_eq!(comparison.diff_pixels, 1);
+    assert_eq!(*comparison.diff_image.get_pixel(3, 4), Rgba([255, 0, 0, 255]));
+}
+
+#[test]
+#[ignore = "the shadow map is a filterable R32Float texture, which needs adapter specific format features software adapters lack"]
+fn golden_boxes() {
+    check_golden::<BoxesExample>("boxes");
+}
+
+#[test]
+fn golden_flipboard() {
+    check_golden::<FlipboardExample>("flipboard");
+}
+
+#[test]
+fn golden_fullscreen_triangle() {
+    check_golden::<FullscreenTriangleExample>("fullscreen_triangle");
+}
+
+#[test]
+fn golden_gltf_viewer() {
+    check_golden::<GLTFViewerExample>("gltf_viewer");
+}
+
+#[test]
+fn golden_imgui() {
+    check_golden::<ImGUIExample>("imgui");
+}
+
+#[test]
+fn golden_pbr() {
+    check_golden::<PBRExample>("pbr");
+}
+
+#[test]
+fn golden_shadow_mapping() {
+    check_golden::<ShadowMappingExample>("shadow_mapping");
+}
+
+#[test]
+#[ignore = "the skybox is baked from assets/textures/papermill.ktx, which isn't checked in"]
+fn golden_skybox() {
+    check_golden::<SkyboxExample>("skybox");
+}
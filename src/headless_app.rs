@@ -0,0 +1,109 @@
+use std::{fs, sync::Arc};
+
+use anyhow::Context;
+use image::RgbaImage;
+use wgpu::{InstanceDescriptor, Backends, RequestAdapterOptions, Limits, DeviceDescriptor, TextureUsages, SurfaceConfiguration, Device, Queue, Texture, TextureFormat};
+
+use crate::{app::{App, AppVariant}, assets_helper::DesktopResourceManager, camera_path::CameraPathDriver, readback, render_target::RenderTarget, timestep::Timestep};
+
+pub const HEADLESS_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+// Drives an App without a window: every frame is rendered into an offscreen texture and read back on demand.
+pub struct HeadlessRunner<T> {
+    device: Device,
+    queue: Arc<Queue>,
+    target: Texture,
+    surface_config: SurfaceConfiguration,
+    timestep: Timestep,
+    camera_path: CameraPathDriver,
+    app_instance: T,
+}
+
+impl<T: App<DesktopResourceManager>> HeadlessRunner<T> {
+    pub async fn new(app_variant: &AppVariant) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(InstanceDescriptor{
+            backends: wgpu::util::backend_bits_from_env().unwrap_or(Backends::all()),
+            dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
+        });
+        let adapter = instance.request_adapter(&RequestAdapterOptions{
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: app_variant.software,
+            compatible_surface: None,
+        }).await.context("No suitable adapter found")?;
+        log::info!("Headless adapter: {:?}", adapter.get_info());
+
+        let (device, queue) = adapter
+            .request_device(
+                &DeviceDescriptor {
+                    label: None,
+                    features: T::get_extra_device_features(app_variant) | (adapter.features() & T::get_optional_device_features(app_variant)),
+                    limits: Limits::default()
+                },
+                None
+            ).await.context("Failed to create device")?;
+        let queue = Arc::new(queue);
+
+        let surface_config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            format: HEADLESS_FORMAT,
+            width: app_variant.width,
+            height: app_variant.height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![HEADLESS_FORMAT]
+        };
+        let target = Self::create_target(&device, &surface_config);
+        let app_instance = T::from_variant(&surface_config, &device, queue.clone(), app_variant, &DesktopResourceManager{});
+
+        Ok(Self { device, queue, target, surface_config, timestep: Timestep::from_variant(app_variant),
+                  camera_path: CameraPathDriver::from_variant(app_variant), app_instance })
+    }
+
+    pub fn render_frame(&mut self, delta: f32) -> anyhow::Result<RgbaImage> {
+        self.camera_path.advance(&mut self.app_instance, delta);
+        self.app_instance.tick(delta);
+        let alpha = self.timestep.advance(&mut self.app_instance, delta);
+        let target = RenderTarget::from_texture(&self.target, self.surface_config.format,
+                                                self.surface_config.width, self.surface_config.height).captured();
+        self.app_instance.render(&target, &self.device, alpha);
+        let mut image = readback::read_texture(&self.device, &self.queue, &self.target, target.format, target.width, target.height)?;
+        // the target stands in for an opaque surface, so alpha written by the examples is meaningless
+        for pixel in image.pixels_mut() {
+            pixel[3] = 255;
+        }
+        Ok(image)
+    }
+
+    fn create_target(device: &Device, config: &SurfaceConfiguration) -> Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Headless target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[],
+        })
+    }
+}
+
+pub async fn run<T: App<DesktopResourceManager>>(title: &str, app_variant: AppVariant) -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut runner = HeadlessRunner::<T>::new(&app_variant).await?;
+    fs::create_dir_all(&app_variant.output_dir)
+        .with_context(|| format!("Failed to create {}", app_variant.output_dir.display()))?;
+
+    for frame in 0..app_variant.frames {
+        let image = runner.render_frame(app_variant.frame_delta)?;
+        let path = app_variant.output_dir.join(format!("{}_{:04}.png", title, frame));
+        image.save(&path).with_context(|| format!("Failed to write {}", path.display()))?;
+        log::info!("Saved {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use imgui::{Context, Io, SuspendedContext};
+
+use crate::input_event::{EventType, InputEvent};
+
+// imgui allows a single active context at a time. Apps keep theirs suspended and activate it only
+// for as long as they use it, so the example selector's menu can be drawn over an example's own ui.
+pub struct ImguiContext {
+    suspended: Option<SuspendedContext>,
+}
+
+impl ImguiContext {
+    // init gets the new context active, e.g. to create a renderer for its font atlas
+    pub fn new<R>(init: impl FnOnce(&mut Context) -> R) -> (Self, R) {
+        let mut context = Context::create();
+        let result = init(&mut context);
+        (Self { suspended: Some(context.suspend()) }, result)
+    }
+
+    pub fn with<R>(&mut self, f: impl FnOnce(&mut Context) -> R) -> R {
+        let mut context = match self.suspended.take().map(SuspendedContext::activate) {
+            Some(Ok(context)) => context,
+            _ => panic!("Another imgui context is active"),
+        };
+        let result = f(&mut context);
+        self.suspended = Some(context.suspend());
+        result
+    }
+}
+
+// Feeds the mouse, wheel, text input and modifiers of the event to imgui
+pub fn handle_input(io: &mut Io, event: &InputEvent) {
+    match event.event_type {
+        EventType::Move => io.mouse_pos = [event.coords[0], event.coords[1]],
+        EventType::Start | EventType::End => {
+            if let Some(button) = io.mouse_down.get_mut(event.button.index()) {
+                *button = event.event_type == EventType::Start;
+            }
+        },
+        EventType::Wheel => {
+            io.mouse_wheel_h += event.coords[0];
+            io.mouse_wheel += event.coords[1];
+        },
+        EventType::Char(c) => io.add_input_character(c),
+        EventType::KeyDown(_) | EventType::KeyUp(_) | EventType::None => (),
+    };
+    io.key_shift = event.modifiers.shift;
+    io.key_ctrl = event.modifiers.ctrl;
+    io.key_alt = event.modifiers.alt;
+    io.key_super = event.modifiers.logo;
+}
@@ -1,68 +1,241 @@
-#[cfg(any(target_os = "macos", target_os = "windows"))]
+use serde::{Deserialize, Serialize};
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
 use winit::event::WindowEvent;
 
-#[derive(Clone)]
+// Pointer id of the mouse, touches use the ids the platform hands out
+pub const MOUSE_POINTER_ID: u64 = u64::MAX;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum EventType {
+    // Pointer pressed, moved and released. Coords are the pointer position in physical pixels,
+    // for the mouse `button` tells which button changed state
     Start,
     Move,
     End,
+    // Coords are the scroll delta in lines, positive y scrolls up/away from the user
+    Wheel,
+    KeyDown(Key),
+    KeyUp(Key),
+    // Text input, already composed and layout aware
+    Char(char),
     None
 }
 
-#[derive(Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Key {
+    A, B, C, D, E, F, G, H, I, J, K, L, M,
+    N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Escape, Space, Enter, Tab, Backspace, Delete, Insert,
+    Left, Right, Up, Down,
+    Home, End, PageUp, PageDown,
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+    Minus, Equals, Comma, Period,
+    Unknown,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u16),
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+#[derive(Debug, Clone)]
 pub struct InputEvent {
     pub event_type: EventType,
     pub coords:     [f32; 2],
+    // MOUSE_POINTER_ID or the id of the touch, lets multi-touch gestures tell fingers apart
+    pub pointer_id: u64,
+    // Button of Start/End mouse events, touches report Left
+    pub button:     MouseButton,
+    pub modifiers:  Modifiers,
 }
 
 impl InputEvent {
     pub(crate) fn default() -> InputEvent {
-        InputEvent { event_type: EventType::None, coords: [0.0; 2] }
+        InputEvent::with_type(EventType::None, [0.0; 2])
+    }
+
+    pub(crate) fn with_type(event_type: EventType, coords: [f32; 2]) -> InputEvent {
+        InputEvent { event_type, coords, pointer_id: MOUSE_POINTER_ID, button: MouseButton::Left, modifiers: Modifiers::default() }
     }
+
+    // Android MotionEvent actions, the pointer id defaults to the first finger
+    #[cfg(target_os = "android")]
     pub(crate) fn new(event_type: i32, x: f32, y: f32) -> InputEvent {
-        InputEvent {
-            event_type: match event_type {
-                0 => EventType::Start,
-                1 => EventType::End,
-                2 => EventType::Move,
-                _ => EventType::None
-            },
-            coords: [x, y]
-        }
+        InputEvent::from_motion(event_type, 0, x, y)
+    }
+
+    #[cfg(target_os = "android")]
+    pub(crate) fn from_motion(action: i32, pointer_id: u64, x: f32, y: f32) -> InputEvent {
+        let event_type = match action {
+            // ACTION_DOWN, ACTION_POINTER_DOWN
+            0 | 5 => EventType::Start,
+            // ACTION_UP, ACTION_CANCEL, ACTION_POINTER_UP
+            1 | 3 | 6 => EventType::End,
+            // ACTION_MOVE
+            2 => EventType::Move,
+            _ => EventType::None
+        };
+        InputEvent { pointer_id, ..InputEvent::with_type(event_type, [x, y]) }
     }
+}
 
-    pub(crate) fn diff(old: &InputEvent, new: &InputEvent) -> InputEvent {
-        InputEvent {
-            event_type: EventType::Move,
-            coords: [new.coords[0] - old.coords[0], new.coords[1] - old.coords[1]]
+impl MouseButton {
+    // Index into imgui's mouse_down
+    pub fn index(&self) -> usize {
+        match self {
+            MouseButton::Left => 0,
+            MouseButton::Right => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Other(button) => 3 + *button as usize,
         }
     }
+}
 
-    #[cfg(any(target_os = "macos", target_os = "windows"))]
-    pub(crate) fn from_winit_event(event: &WindowEvent) -> InputEvent {
-        // match event {
-        //     WindowEvent::CursorMoved { position, .. } =>
-        //         InputEvent{ event_type:  EventType::Move, coords: [position.x as f32, position.y as f32] },
-        //     _ => InputEvent::default()
-        // }
+// winit doesn't repeat the cursor position and modifiers on button and key events, so the translation keeps them around
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+#[derive(Default)]
+pub struct WinitInputTranslator {
+    cursor: [f32; 2],
+    modifiers: Modifiers,
+}
 
-        use winit::event::ElementState;
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+impl WinitInputTranslator {
+    pub(crate) fn translate(&mut self, event: &WindowEvent) -> InputEvent {
+        use winit::event::{ElementState, MouseScrollDelta, TouchPhase};
 
-        match event {
+        let event = match event {
+            WindowEvent::ModifiersChanged(state) => {
+                self.modifiers = Modifiers { shift: state.shift(), ctrl: state.ctrl(), alt: state.alt(), logo: state.logo() };
+                InputEvent::default()
+            },
             WindowEvent::MouseInput { button, state, .. } => {
-                match state {
-                    ElementState::Pressed => {
-                        InputEvent{ event_type: EventType::Start, coords: [0.0, 0.0] }
-                    },
-                    ElementState::Released => {
-                        InputEvent{ event_type: EventType::End, coords: [0.0, 0.0] }
-                    },
-                }
+                let event_type = match state {
+                    ElementState::Pressed => EventType::Start,
+                    ElementState::Released => EventType::End,
+                };
+                let button = match button {
+                    winit::event::MouseButton::Left => MouseButton::Left,
+                    winit::event::MouseButton::Right => MouseButton::Right,
+                    winit::event::MouseButton::Middle => MouseButton::Middle,
+                    winit::event::MouseButton::Other(button) => MouseButton::Other(*button),
+                };
+                InputEvent { button, ..InputEvent::with_type(event_type, self.cursor) }
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = [position.x as f32, position.y as f32];
+                InputEvent::with_type(EventType::Move, self.cursor)
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                // pixel deltas come from touchpads, roughly a line per 20 pixels
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => [*x, *y],
+                    MouseScrollDelta::PixelDelta(position) => [position.x as f32 / 20.0, position.y as f32 / 20.0],
+                };
+                InputEvent::with_type(EventType::Wheel, lines)
+            },
+            WindowEvent::Touch(touch) => {
+                let event_type = match touch.phase {
+                    TouchPhase::Started => EventType::Start,
+                    TouchPhase::Moved => EventType::Move,
+                    TouchPhase::Ended | TouchPhase::Cancelled => EventType::End,
+                };
+                InputEvent { pointer_id: touch.id, ..InputEvent::with_type(event_type, [touch.location.x as f32, touch.location.y as f32]) }
             },
-                
-            WindowEvent::CursorMoved { position, .. } =>
-                InputEvent{ event_type:  EventType::Move, coords: [position.x as f32, position.y as f32] },
+            WindowEvent::KeyboardInput { input, .. } => {
+                let key = input.virtual_keycode.map_or(Key::Unknown, Key::from_winit);
+                let event_type = match input.state {
+                    ElementState::Pressed => EventType::KeyDown(key),
+                    ElementState::Released => EventType::KeyUp(key),
+                };
+                InputEvent::with_type(event_type, self.cursor)
+            },
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() =>
+                InputEvent::with_type(EventType::Char(*c), self.cursor),
             _ => InputEvent::default()
+        };
+        InputEvent { modifiers: self.modifiers, ..event }
+    }
+}
+
+impl Key {
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    fn from_winit(key: winit::event::VirtualKeyCode) -> Key {
+        use winit::event::VirtualKeyCode as VK;
+        match key {
+            VK::A => Key::A, VK::B => Key::B, VK::C => Key::C, VK::D => Key::D, VK::E => Key::E,
+            VK::F => Key::F, VK::G => Key::G, VK::H => Key::H, VK::I => Key::I, VK::J => Key::J,
+            VK::K => Key::K, VK::L => Key::L, VK::M => Key::M, VK::N => Key::N, VK::O => Key::O,
+            VK::P => Key::P, VK::Q => Key::Q, VK::R => Key::R, VK::S => Key::S, VK::T => Key::T,
+            VK::U => Key::U, VK::V => Key::V, VK::W => Key::W, VK::X => Key::X, VK::Y => Key::Y,
+            VK::Z => Key::Z,
+            VK::Key0 | VK::Numpad0 => Key::Key0, VK::Key1 | VK::Numpad1 => Key::Key1,
+            VK::Key2 | VK::Numpad2 => Key::Key2, VK::Key3 | VK::Numpad3 => Key::Key3,
+            VK::Key4 | VK::Numpad4 => Key::Key4, VK::Key5 | VK::Numpad5 => Key::Key5,
+            VK::Key6 | VK::Numpad6 => Key::Key6, VK::Key7 | VK::Numpad7 => Key::Key7,
+            VK::Key8 | VK::Numpad8 => Key::Key8, VK::Key9 | VK::Numpad9 => Key::Key9,
+            VK::F1 => Key::F1, VK::F2 => Key::F2, VK::F3 => Key::F3, VK::F4 => Key::F4,
+            VK::F5 => Key::F5, VK::F6 => Key::F6, VK::F7 => Key::F7, VK::F8 => Key::F8,
+            VK::F9 => Key::F9, VK::F10 => Key::F10, VK::F11 => Key::F11, VK::F12 => Key::F12,
+            VK::Escape => Key::Escape, VK::Space => Key::Space, VK::Return | VK::NumpadEnter => Key::Enter,
+            VK::Tab => Key::Tab, VK::Back => Key::Backspace, VK::Delete => Key::Delete, VK::Insert => Key::Insert,
+            VK::Left => Key::Left, VK::Right => Key::Right, VK::Up => Key::Up, VK::Down => Key::Down,
+            VK::Home => Key::Home, VK::End => Key::End, VK::PageUp => Key::PageUp, VK::PageDown => Key::PageDown,
+            VK::LShift => Key::LShift, VK::RShift => Key::RShift,
+            VK::LControl => Key::LControl, VK::RControl => Key::RControl,
+            VK::LAlt => Key::LAlt, VK::RAlt => Key::RAlt,
+            VK::Minus | VK::NumpadSubtract => Key::Minus, VK::Equals => Key::Equals,
+            VK::Comma => Key::Comma, VK::Period => Key::Period,
+            _ => Key::Unknown,
         }
     }
-}
\ No newline at end of file
+
+    // Android KeyEvent key codes (AKEYCODE_*)
+    #[cfg(target_os = "android")]
+    pub(crate) fn from_android(key_code: i32) -> Key {
+        const LETTERS: [Key; 26] = [Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+                                    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z];
+        const DIGITS: [Key; 10] = [Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9];
+        const FUNCTION_KEYS: [Key; 12] = [Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12];
+        match key_code {
+            7..=16 => DIGITS[(key_code - 7) as usize],
+            29..=54 => LETTERS[(key_code - 29) as usize],
+            131..=142 => FUNCTION_KEYS[(key_code - 131) as usize],
+            19 => Key::Up, 20 => Key::Down, 21 => Key::Left, 22 => Key::Right,
+            55 => Key::Comma, 56 => Key::Period,
+            57 => Key::LAlt, 58 => Key::RAlt, 59 => Key::LShift, 60 => Key::RShift,
+            61 => Key::Tab, 62 => Key::Space, 66 => Key::Enter, 67 => Key::Backspace,
+            69 => Key::Minus, 70 => Key::Equals,
+            92 => Key::PageUp, 93 => Key::PageDown,
+            111 => Key::Escape, 112 => Key::Delete, 113 => Key::LControl, 114 => Key::RControl,
+            122 => Key::Home, 123 => Key::End, 124 => Key::Insert,
+            _ => Key::Unknown,
+        }
+    }
+}
+
+impl Modifiers {
+    // Android KeyEvent meta state
+    #[cfg(target_os = "android")]
+    pub(crate) fn from_android(meta_state: i32) -> Modifiers {
+        Modifiers {
+            shift: meta_state & 0x1 != 0,
+            alt: meta_state & 0x2 != 0,
+            ctrl: meta_state & 0x1000 != 0,
+            logo: meta_state & 0x10000 != 0,
+        }
+    }
+}
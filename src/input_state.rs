@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::input_event::{EventType, InputEvent, Key, MouseButton, MOUSE_POINTER_ID};
+
+// How many pixels two fingers have to spread apart to zoom as much as one wheel line
+const PINCH_PIXELS_PER_LINE: f32 = 50.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    // Held while the key is down
+    Key(Key),
+    // Held while the button is down, its value is the mouse movement meanwhile
+    Mouse(MouseButton),
+    // Held while exactly that many fingers touch the screen, its value is the movement of their centroid
+    Touch(usize),
+    // Scroll delta in lines
+    Wheel,
+    // Change of the distance between two fingers, in wheel lines
+    Pinch,
+}
+
+// Named actions ("orbit", "zoom", "move_left") and the bindings triggering them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ActionMap {
+    actions: HashMap<String, Vec<Binding>>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        let actions = [
+            ("orbit", vec![Binding::Mouse(MouseButton::Left), Binding::Touch(1)]),
+            ("look", vec![Binding::Mouse(MouseButton::Left), Binding::Touch(1)]),
+            ("pan", vec![Binding::Mouse(MouseButton::Right), Binding::Mouse(MouseButton::Middle), Binding::Touch(2)]),
+            ("zoom", vec![Binding::Wheel, Binding::Pinch]),
+            ("move_left", vec![Binding::Key(Key::A), Binding::Key(Key::Left)]),
+            ("move_right", vec![Binding::Key(Key::D), Binding::Key(Key::Right)]),
+            ("move_forward", vec![Binding::Key(Key::W), Binding::Key(Key::Up)]),
+            ("move_back", vec![Binding::Key(Key::S), Binding::Key(Key::Down)]),
+            ("move_up", vec![Binding::Key(Key::E)]),
+            ("move_down", vec![Binding::Key(Key::Q)]),
+            ("move_fast", vec![Binding::Key(Key::LShift), Binding::Key(Key::RShift)]),
+            ("move_slow", vec![Binding::Key(Key::LControl), Binding::Key(Key::RControl)]),
+            ("launch", vec![Binding::Key(Key::Space)]),
+            ("frame", vec![Binding::Key(Key::F)]),
+            ("toggle_camera", vec![Binding::Key(Key::C)]),
+            ("record_keyframe", vec![Binding::Key(Key::K)]),
+            ("toggle_animation", vec![Binding::Key(Key::P)]),
+            ("next_animation", vec![Binding::Key(Key::N)]),
+        ];
+        Self { actions: actions.into_iter().map(|(name, bindings)| (name.to_string(), bindings)).collect() }
+    }
+}
+
+impl ActionMap {
+    // A json object of action names to binding lists, e.g. {"zoom": ["Wheel", {"Key": "Z"}]}.
+    // Actions the config doesn't mention keep their default bindings.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_json(&json).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let overrides: ActionMap = serde_json::from_str(json)?;
+        let mut map = Self::default();
+        map.actions.extend(overrides.actions);
+        Ok(map)
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map_or(&[], |bindings| bindings.as_slice())
+    }
+}
+
+// Accumulates the events of a frame into what is held down and how much pointers moved, so consumers
+// query state instead of diffing against the previous event themselves. The runner calls end_frame
+// once the app has seen the frame's state.
+pub struct InputState {
+    actions: ActionMap,
+    keys: HashSet<Key>,
+    buttons: HashSet<MouseButton>,
+    cursor: Option<[f32; 2]>,
+    touches: BTreeMap<u64, [f32; 2]>,
+
+    // reset every frame
+    keys_released: HashSet<Key>,
+    mouse_delta: [f32; 2],
+    touch_delta: [f32; 2],
+    wheel: [f32; 2],
+    pinch: f32,
+}
+
+impl InputState {
+    pub fn new(actions: ActionMap) -> Self {
+        Self {
+            actions,
+            keys: HashSet::new(),
+            buttons: HashSet::new(),
+            cursor: None,
+            touches: BTreeMap::new(),
+            keys_released: HashSet::new(),
+            mouse_delta: [0.0; 2],
+            touch_delta: [0.0; 2],
+            wheel: [0.0; 2],
+            pinch: 0.0,
+        }
+    }
+
+    pub fn handle(&mut self, event: &InputEvent) {
+        match (&event.event_type, event.pointer_id) {
+            (EventType::KeyDown(key), _) => {
+                self.keys.insert(*key);
+            },
+            (EventType::KeyUp(key), _) => {
+                if self.keys.remove(key) {
+                    self.keys_released.insert(*key);
+                }
+            },
+            (EventType::Start, MOUSE_POINTER_ID) => { self.buttons.insert(event.button); },
+            (EventType::End, MOUSE_POINTER_ID) => { self.buttons.remove(&event.button); },
+            (EventType::Move, MOUSE_POINTER_ID) => {
+                if let Some(cursor) = self.cursor {
+                    self.mouse_delta[0] += event.coords[0] - cursor[0];
+                    self.mouse_delta[1] += event.coords[1] - cursor[1];
+                }
+                self.cursor = Some(event.coords);
+            },
+            (EventType::Start, id) => { self.touches.insert(id, event.coords); },
+            (EventType::End, id) => { self.touches.remove(&id); },
+            (EventType::Move, id) => self.touch_moved(id, event.coords),
+            (EventType::Wheel, _) => {
+                self.wheel[0] += event.coords[0];
+                self.wheel[1] += event.coords[1];
+            },
+            (EventType::Char(_) | EventType::None, _) => (),
+        }
+    }
+
+    fn touch_moved(&mut self, id: u64, coords: [f32; 2]) {
+        let Some(prev) = self.touches.insert(id, coords) else { return };
+        let count = self.touches.len() as f32;
+        self.touch_delta[0] += (coords[0] - prev[0]) / count;
+        self.touch_delta[1] += (coords[1] - prev[1]) / count;
+        if self.touches.len() == 2 {
+            let other = *self.touches.iter().find(|(other_id, _)| **other_id != id).unwrap().1;
+            let distance = |a: [f32; 2]| ((a[0] - other[0]).powi(2) + (a[1] - other[1]).powi(2)).sqrt();
+            self.pinch += (distance(coords) - distance(prev)) / PINCH_PIXELS_PER_LINE;
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.keys_released.clear();
+        self.mouse_delta = [0.0; 2];
+        self.touch_delta = [0.0; 2];
+        self.wheel = [0.0; 2];
+        self.pinch = 0.0;
+    }
+
+    // Whether any binding of the action is held
+    pub fn is_active(&self, action: &str) -> bool {
+        self.actions.bindings(action).iter().any(|binding| match binding {
+            Binding::Key(key) => self.keys.contains(key),
+            Binding::Mouse(button) => self.buttons.contains(button),
+            Binding::Touch(fingers) => self.touches.len() == *fingers,
+            Binding::Wheel => self.wheel != [0.0; 2],
+            Binding::Pinch => self.pinch != 0.0,
+        })
+    }
+
+    // Whether a key bound to the action went up this frame
+    pub fn was_released(&self, action: &str) -> bool {
+        self.actions.bindings(action).iter().any(|binding| matches!(binding, Binding::Key(key) if self.keys_released.contains(key)))
+    }
+
+    // This frame's movement summed over the action's held bindings, pixels for drags and lines for the wheel and pinches
+    pub fn value(&self, action: &str) -> [f32; 2] {
+        self.actions.bindings(action).iter().fold([0.0; 2], |value, binding| {
+            let delta = match binding {
+                Binding::Key(_) => [0.0; 2],
+                Binding::Mouse(button) if self.buttons.contains(button) => self.mouse_delta,
+                Binding::Touch(fingers) if self.touches.len() == *fingers => self.touch_delta,
+                Binding::Mouse(_) | Binding::Touch(_) => [0.0; 2],
+                Binding::Wheel => self.wheel,
+                Binding::Pinch => [0.0, self.pinch],
+            };
+            [value[0] + delta[0], value[1] + delta[1]]
+        })
+    }
+}
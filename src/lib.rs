@@ -1,3 +1,6 @@
+// The library is only the android entry point, elsewhere nothing in it is reachable
+#![cfg_attr(not(target_os = "android"), allow(dead_code))]
+
 #[cfg(target_os = "android")]
 #[path = "./android/ffi.rs"]
 mod android;
@@ -8,7 +11,12 @@ mod app;
 mod camera;
 mod assets_helper;
 mod model;
+mod animation;
 mod input_event;
+mod input_state;
+mod render_target;
+mod profiler;
+mod imgui_context;
 
 #[path = "./examples/imgui_example/imgui_example.rs"]
 mod imgui_example;
\ No newline at end of file
@@ -3,11 +3,21 @@ use structopt::StructOpt;
 use windowed_app::run;
 
 mod windowed_app;
+mod capture;
+mod headless_app;
+mod readback;
 mod app;
 mod camera;
+mod camera_path;
 mod assets_helper;
 mod model;
+mod animation;
 mod input_event;
+mod input_state;
+mod render_target;
+mod profiler;
+mod imgui_context;
+mod timestep;
 
 #[path = "./pieces/geometry_primitives.rs"]
 mod geometry_primitives;
@@ -18,9 +28,36 @@ extern crate nalgebra_glm as glm;
 
 #[path = "./examples/pbr/pbr.rs"]
 mod pbr;
-use pbr::PBRExample;
+#[path = "./examples/boxes/boxes.rs"]
+mod boxes;
+#[path = "./examples/flipboard/flipboard.rs"]
+mod flipboard;
+#[path = "./examples/fullscreen_tiangle/fullscreen_triangle.rs"]
+mod fullscreen_triangle;
+#[path = "./examples/gltf/gltf_viewer.rs"]
+mod gltf_viewer;
+#[path = "./examples/imgui_example/imgui_example.rs"]
+mod imgui_example;
+#[path = "./examples/shadow_mapping/shadow_mapping.rs"]
+mod shadow_mapping;
+#[path = "./examples/skybox/skybox_example.rs"]
+mod skybox_example;
+#[path = "./examples/examples.rs"]
+mod examples;
+
+#[cfg(test)]
+mod golden_tests;
+
+use examples::ExampleSelector;
 
 fn main() {
     let app_variant = AppVariant::from_args();
-    pollster::block_on(run::<PBRExample>("PBRExample", app_variant));
+    if app_variant.headless {
+        if let Err(error) = pollster::block_on(headless_app::run::<ExampleSelector>(app_variant.example.name(), app_variant.clone())) {
+            eprintln!("Headless run failed - {:?}", error);
+            std::process::exit(1);
+        }
+    } else {
+        pollster::block_on(run::<ExampleSelector>("wgpu playground", app_variant));
+    }
 }
\ No newline at end of file
@@ -1,14 +1,17 @@
-use std::{io::{Cursor, BufReader}, borrow::Borrow, iter};
-use std::path::PathBuf;
+use std::{borrow::Borrow, collections::HashMap};
+use std::path::Path;
 
-use glm::mat4;
-use gltf::{Gltf, material::AlphaMode, Semantic, texture::{MagFilter, MinFilter}};
+use anyhow::{bail, Context};
+
+use gltf::{Gltf, material::AlphaMode, texture::{MagFilter, MinFilter}};
 use image::GenericImageView;
-use rand::seq::index::sample;
-use wgpu::{util::{DeviceExt, BufferInitDescriptor}, BufferUsages, Device, Queue, BindGroup, BindGroupDescriptor, BindGroupLayoutDescriptor, BindGroupLayoutEntry, ShaderStages, BindGroupEntry, Texture, TextureViewDescriptor, TextureView, Sampler, SamplerDescriptor};
+use wgpu::{util::{DeviceExt, BufferInitDescriptor}, BufferUsages, Device, Queue, BindGroup, BindGroupDescriptor, BindGroupLayoutDescriptor, BindGroupLayoutEntry, ShaderStages, BindGroupEntry, TextureView, Sampler, SamplerDescriptor, PrimitiveTopology, RenderPipeline};
 
+use crate::animation::{Animation, NodeTransform, Playback};
 use crate::assets_helper::ResourceManager;
+use crate::camera::{Frustum, Pose, Projection};
 
+// The NodeUniforms of a model's nodes, picked by a dynamic offset, the joint matrices of all its skins and its MorphDeltas
 pub const NOD_MM_BGL:  BindGroupLayoutDescriptor = BindGroupLayoutDescriptor{
     label: Some("mm_bgl"),
     entries: &[BindGroupLayoutEntry{
@@ -16,12 +19,35 @@ pub const NOD_MM_BGL:  BindGroupLayoutDescriptor = BindGroupLayoutDescriptor{
         visibility: ShaderStages::VERTEX,
         ty: wgpu::BindingType::Buffer {
             ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<NodeUniform>() as u64),
+        },
+        count: None,
+    },
+    BindGroupLayoutEntry{
+        binding: 1,
+        visibility: ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None
+        },
+        count: None,
+    },
+    BindGroupLayoutEntry{
+        binding: 2,
+        visibility: ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
             has_dynamic_offset: false,
             min_binding_size: None
         },
         count: None,
     }],
 };
+
+// How many morph targets of a mesh the shaders blend, the ones past it are ignored
+const MAX_MORPH_TARGETS: usize = 8;
 // pub const MATERIAL_BGL: BindGroupLayoutDescriptor = BindGroupLayoutDescriptor{
 //     label: Some("Texture bgl"),
 //     entries: &[
@@ -149,7 +175,175 @@ struct Vertex {
     normal: [f32; 3],
     uv0:    [f32; 2],
     uv1:    [f32; 2],
-    color:  [f32; 3]
+    color:  [f32; 3],
+    // w is the handedness of the bitangent, all zeros when the primitive has none
+    tangent: [f32; 4],
+    // indices into the skin's joints and how much each of them moves the vertex
+    joints:  [u32; 4],
+    weights: [f32; 4],
+    // index of the MorphDelta of the vertex' first morph target, the other targets follow it
+    morph_index: u32,
+}
+
+// How far a morph target moves a vertex at weight 1
+#[repr(C)]
+#[derive(Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MorphDelta {
+    position: [f32; 4],
+    normal:   [f32; 4],
+    tangent:  [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct NodeUniform {
+    transform:   [[f32; 4]; 4],
+    // 0 for nodes without a skin, the shaders skip skinning then
+    joint_count: u32,
+    morph_target_count: u32,
+    // index of the skin's first joint matrix
+    joint_offset: u32,
+    _padding:    u32,
+    morph_weights: [[f32; 4]; MAX_MORPH_TARGETS / 4],
+}
+
+// One buffer for the uniforms of all nodes and one for all joint matrices, so moving nodes is
+// two writes and a model draws with a single bind group
+struct NodeBindings {
+    bind_group:     BindGroup,
+    node_buffer:    wgpu::Buffer,
+    joint_buffer:   wgpu::Buffer,
+    // between the NodeUniforms of neighbouring nodes, what dynamic offsets have to be aligned to
+    node_stride:    u32,
+}
+
+impl NodeBindings {
+    fn new(device: &Device, nodes: &[Node], skins: &[Skin], world_matrices: &[glm::Mat4], morph_deltas: &[MorphDelta]) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment;
+        let node_stride = (std::mem::size_of::<NodeUniform>() as u32).div_ceil(alignment) * alignment;
+        let (node_data, joint_matrices) = Self::data(nodes, skins, world_matrices, node_stride);
+        // zero sized buffers can't be bound
+        let placeholder = |data: Vec<u8>, size: usize| if data.is_empty() { vec![0; size] } else { data };
+        let node_buffer = device.create_buffer_init(&BufferInitDescriptor{
+            label: Some("Node uniforms buff"),
+            contents: &placeholder(node_data, node_stride as usize),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let joint_buffer = device.create_buffer_init(&BufferInitDescriptor{
+            label: Some("Joint matrices buff"),
+            contents: &placeholder(bytemuck::cast_slice(&joint_matrices).to_vec(), std::mem::size_of::<[[f32; 4]; 4]>()),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let morph_buffer = device.create_buffer_init(&BufferInitDescriptor{
+            label: Some("Morph deltas buff"),
+            contents: &placeholder(bytemuck::cast_slice(morph_deltas).to_vec(), std::mem::size_of::<MorphDelta>()),
+            usage: BufferUsages::STORAGE,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor{
+            label: Some("Model matrix bg"),
+            layout: &device.create_bind_group_layout(&NOD_MM_BGL),
+            entries: &[BindGroupEntry{
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &node_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<NodeUniform>() as u64),
+                }),
+            },
+            BindGroupEntry{
+                binding: 1,
+                resource: joint_buffer.as_entire_binding(),
+            },
+            BindGroupEntry{
+                binding: 2,
+                resource: morph_buffer.as_entire_binding(),
+            }],
+        });
+        Self { bind_group, node_buffer, joint_buffer, node_stride }
+    }
+
+    // The NodeUniforms node_stride apart and the joint matrices of every skinned node one after another
+    fn data(nodes: &[Node], skins: &[Skin], world_matrices: &[glm::Mat4], node_stride: u32) -> (Vec<u8>, Vec<[[f32; 4]; 4]>) {
+        let mut node_data = vec![0u8; nodes.len() * node_stride as usize];
+        let mut joint_matrices = Vec::new();
+        for (i, node) in nodes.iter().enumerate() {
+            let uniform = GLTFModel::node_uniform(node, skins, &world_matrices[i], joint_matrices.len() as u32);
+            let offset = i * node_stride as usize;
+            node_data[offset..offset + std::mem::size_of::<NodeUniform>()].copy_from_slice(bytemuck::bytes_of(&uniform));
+            if let Some(node_joint_matrices) = GLTFModel::joint_matrices(node, skins, &world_matrices[i], world_matrices) {
+                joint_matrices.extend(node_joint_matrices);
+            }
+        }
+        (node_data, joint_matrices)
+    }
+
+    fn write(&self, queue: &Queue, nodes: &[Node], skins: &[Skin], world_matrices: &[glm::Mat4]) {
+        let (node_data, joint_matrices) = Self::data(nodes, skins, world_matrices, self.node_stride);
+        if !node_data.is_empty() {
+            queue.write_buffer(&self.node_buffer, 0, &node_data);
+        }
+        if !joint_matrices.is_empty() {
+            queue.write_buffer(&self.joint_buffer, 0, bytemuck::cast_slice(&joint_matrices));
+        }
+    }
+}
+
+// A triangle list primitive as mikktspace sees it. Every face corner is a vertex of its own, so
+// corners sharing a vertex on both sides of a mirrored uv seam can get different tangents.
+struct TangentSpace {
+    corners: Vec<Vertex>,
+    // the uv set of the normal texture
+    uv_set:  u32,
+}
+
+impl TangentSpace {
+    fn vertex(&self, face: usize, vert: usize) -> &Vertex {
+        &self.corners[face * 3 + vert]
+    }
+
+    // Welds the corners back together wherever they came out identical, tangent included
+    fn weld(self) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = vec![];
+        let mut welded: HashMap<Vec<u8>, u32> = HashMap::new();
+        let indices = self.corners.iter().map(|corner| *welded.entry(bytemuck::bytes_of(corner).to_vec()).or_insert_with(|| {
+            vertices.push(*corner);
+            vertices.len() as u32 - 1
+        })).collect();
+        (vertices, indices)
+    }
+}
+
+// MikkTSpace tangents of a triangle list, returns the re-indexed vertices since seams split some of them
+fn generate_tangents(vertices: &[Vertex], indices: &[u32], uv_set: u32) -> Option<(Vec<Vertex>, Vec<u32>)> {
+    let mut tangent_space = TangentSpace { corners: indices.iter().map(|index| vertices[*index as usize]).collect(), uv_set };
+    mikktspace::generate_tangents(&mut tangent_space).then(|| tangent_space.weld())
+}
+
+impl mikktspace::Geometry for TangentSpace {
+    fn num_faces(&self) -> usize {
+        self.corners.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).pos
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let vertex = self.vertex(face, vert);
+        if self.uv_set == 1 { vertex.uv1 } else { vertex.uv0 }
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corners[face * 3 + vert].tangent = tangent;
+    }
 }
 
 #[derive(Default)]
@@ -230,10 +424,66 @@ struct TexCoordSets {
 //     }
 // }
 
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl Aabb {
+    fn empty() -> Self {
+        Self { min: glm::Vec3::repeat(f32::MAX), max: glm::Vec3::repeat(f32::MIN) }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.min.x > self.max.x
+    }
+
+    fn extend(&mut self, point: &glm::Vec3) {
+        self.min = glm::min2(&self.min, point);
+        self.max = glm::max2(&self.max, point);
+    }
+
+    fn union(&mut self, other: &Aabb) {
+        if !other.is_empty() {
+            self.extend(&other.min);
+            self.extend(&other.max);
+        }
+    }
+
+    // Box around the eight transformed corners
+    fn transform(&self, matrix: &glm::Mat4) -> Aabb {
+        let mut result = Aabb::empty();
+        if self.is_empty() {
+            return result;
+        }
+        for corner in 0..8 {
+            let point = glm::vec3(
+                if corner & 1 == 0 { self.min.x } else { self.max.x },
+                if corner & 2 == 0 { self.min.y } else { self.max.y },
+                if corner & 4 == 0 { self.min.z } else { self.max.z },
+            );
+            result.extend(&(matrix * point.push(1.0)).xyz());
+        }
+        result
+    }
+}
+
 struct Primitive {
     first_index:     u32,
     index_count:     u32,
     material_index:  u32,
+    // strips, fans and loops are unrolled into lists at load time
+    topology:        PrimitiveTopology,
+    // in the node's space
+    bounds:          Aabb,
+}
+
+// Primitives drawn and skipped by the frustum culling in a draw_model call
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DrawStats {
+    pub visible: u32,
+    pub culled:  u32,
 }
 
 struct Mesh {
@@ -241,26 +491,126 @@ struct Mesh {
 }
 
 pub struct Node {
+    name:           Option<String>,
     parent:         Option<u32>,
-    //children:       Vec<u32>,
+    children:       Vec<u32>,
     mesh:           Mesh,
-    
-    matrix:             glm::Mat4,
-    // translation:        glm::Vec3,
-    // scale:              glm::Vec3,
-    // rotation:           glm::Quat,
+    // of the mesh in the node's space
+    bounds:         Aabb,
+    // index into the document's cameras
+    camera:         Option<usize>,
+    // index into the document's skins
+    skin:           Option<usize>,
+    // index of the node in the document
+    source:         usize,
+
+    pub(crate) transform:   NodeTransform,
+    // one per morph target of the mesh
+    pub(crate) morph_weights: Vec<f32>,
 }
 
 impl Default for Node {
     fn default() -> Self {
         Self {
+            name: None,
             parent: None,
+            children: Vec::new(),
             mesh: Mesh { primitives: Vec::new() },
-            matrix: glm::Mat4::identity(),
+            bounds: Aabb::empty(),
+            camera: None,
+            skin: None,
+            source: 0,
+            transform: NodeTransform::default(),
+            morph_weights: Vec::new(),
         }
     }
 }
 
+impl Node {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    // Indices into the model's nodes
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        self.children.iter().map(|child| *child as usize)
+    }
+}
+
+// The root nodes of one of the document's scenes, nodes shared by several scenes are loaded once
+pub struct Scene {
+    pub name:   Option<String>,
+    roots:      Vec<usize>,
+    // the roots and all their descendants, what gets drawn while the scene is the model's one
+    nodes:      Vec<usize>,
+}
+
+impl Scene {
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+}
+
+// Joints are indices into the model's nodes, None for the ones outside the loaded scenes
+struct Skin {
+    joints:                 Vec<Option<u32>>,
+    inverse_bind_matrices:  Vec<glm::Mat4>,
+}
+
+impl Skin {
+    fn new(skin: &gltf::Skin, nodes: &[Node], buffer_data: &[Vec<u8>]) -> Self {
+        let joints = skin.joints().map(|joint| nodes.iter().position(|node| node.source == joint.index()).map(|index| index as u32)).collect();
+        let reader = skin.reader(|buffer| buffer_data.get(buffer.index()).map(Vec::as_slice));
+        // without them the joints are in the bind pose already
+        let inverse_bind_matrices = reader.read_inverse_bind_matrices()
+            .map_or(vec![], |iter| iter.map(glm::Mat4::from).collect());
+        Self { joints, inverse_bind_matrices }
+    }
+
+    // Moves the vertices of the skinned node's mesh from the bind pose to the joints' current pose,
+    // in the node's space since the shaders apply its transform after skinning
+    fn joint_matrices(&self, node_matrix: &glm::Mat4, world_matrices: &[glm::Mat4]) -> Vec<glm::Mat4> {
+        let inverse_node_matrix = glm::inverse(node_matrix);
+        self.joints.iter().enumerate().map(|(i, joint)| {
+            let joint_matrix = joint.map_or(glm::Mat4::identity(), |joint| world_matrices[joint as usize]);
+            let inverse_bind_matrix = self.inverse_bind_matrices.get(i).copied().unwrap_or_else(glm::Mat4::identity);
+            inverse_node_matrix * joint_matrix * inverse_bind_matrix
+        }).collect()
+    }
+}
+
+// A camera authored in the document, placed where its node is
+pub struct SceneCamera {
+    pub name:       Option<String>,
+    pub projection: Projection,
+    // vertical, in radians. Orthographic cameras keep the fov the viewer would otherwise use
+    pub yfov:       f32,
+    pub znear:      f32,
+    // None for infinite perspective projections
+    pub zfar:       Option<f32>,
+    pub pose:       Pose,
+}
+
+impl SceneCamera {
+    fn new(camera: &gltf::Camera, world_matrix: &glm::Mat4) -> Self {
+        let (projection, yfov, znear, zfar) = match camera.projection() {
+            gltf::camera::Projection::Perspective(perspective) =>
+                (Projection::Perspective, perspective.yfov(), perspective.znear(), perspective.zfar()),
+            // ymag is half the height of the view volume
+            gltf::camera::Projection::Orthographic(orthographic) =>
+                (Projection::Orthographic { height: orthographic.ymag() * 2.0 }, 45.0, orthographic.znear(), Some(orthographic.zfar())),
+        };
+        // glTF cameras look down -z with +y up like ours do, the node's scale is dropped
+        let axis = |column: usize| glm::normalize(&glm::vec3(world_matrix[(0, column)], world_matrix[(1, column)], world_matrix[(2, column)]));
+        let rotation = glm::Mat3::from_columns(&[axis(0), axis(1), axis(2)]);
+        let pose = Pose {
+            position: glm::vec3(world_matrix[(0, 3)], world_matrix[(1, 3)], world_matrix[(2, 3)]),
+            orientation: glm::mat3_to_quat(&rotation),
+        };
+        Self { name: camera.name().map(str::to_string), projection, yfov, znear, zfar, pose }
+    }
+}
+
 pub struct GLTFModel {
     nodes:              Vec<Node>,
     
@@ -271,7 +621,20 @@ pub struct GLTFModel {
     //textures:           Vec<Option<BindGroup>>,
     //textures:           Vec<Option<Texture>>,
     materials:          Vec<Material>,
-    nodes_matrices:     Vec<BindGroup>
+    // rewritten when an animation moves the nodes
+    node_bindings:      NodeBindings,
+    world_matrices:     Vec<glm::Mat4>,
+    scenes:             Vec<Scene>,
+    // index into scenes
+    scene:              usize,
+    // of the scene's nodes
+    bounds:             Aabb,
+    cameras:            Vec<SceneCamera>,
+    skins:              Vec<Skin>,
+    animations:         Vec<Animation>,
+    playback:           Playback,
+    // the nodes need posing and writing even though the playback is paused, e.g. after a seek
+    posed:              bool,
 }
 
 impl GLTFModel {
@@ -279,38 +642,37 @@ impl GLTFModel {
         let mut index_buffer = Vec::new();
         let mut vertex_buffer = Vec::new();
         let mut nodes = Vec::<Node>::new();
+        let mut morph_deltas = Vec::new();
         
-        if let Some(scene) = gltf.scenes().nth(0) {
-            scene.nodes().for_each(|node|{
+        // parse_gltf appends the default material after the file's ones
+        let default_material = materials.len() as u32 - 1;
+        let scenes: Vec<Scene> = gltf.scenes().map(|scene| {
+            let roots: Vec<usize> = scene.nodes().map(|node|{
                 // let some = gltf.accessors().nth(node.mesh().unwrap().primitives().nth(0).unwrap().attributes().find(|semantic|{
                 //     semantic.0 == Semantic::Positions
                 // }));
-                Self::load_node(&gltf, &node, &gltf, None, &mut index_buffer, &mut vertex_buffer, &buffer_data, &mut nodes);
-            });
-        }
-        let nm_bgl = device.create_bind_group_layout(&NOD_MM_BGL);
-        let nodes_matrices: Vec<BindGroup> = nodes.iter().map(|node| {
-            let mut node_matrix = node.matrix;
-            let mut current_parent_index = node.parent;
-            while let Some(index) = current_parent_index {
-                node_matrix = nodes[index as usize].matrix * node_matrix;
-                current_parent_index = nodes[index as usize].parent;
-            };
-            let data: [[f32; 4]; 4] = node_matrix.into();
-            let buffer = device.create_buffer_init(&BufferInitDescriptor{
-                label: Some("model matrix buff"),
-                contents: bytemuck::cast_slice(&data),
-                usage: BufferUsages::UNIFORM,
-            });
-            device.create_bind_group(&BindGroupDescriptor{
-                label: Some("Model matrix bg"),
-                layout: &nm_bgl,
-                entries: &[BindGroupEntry{
-                    binding: 0,
-                    resource: buffer.as_entire_binding(),
-                }],
-            })
+                nodes.iter().position(|loaded| loaded.source == node.index()).unwrap_or_else(|| {
+                    Self::load_node(default_material, &node, &gltf, None, &mut index_buffer, &mut vertex_buffer, &mut morph_deltas, &buffer_data, &mut nodes);
+                    nodes.len() - 1
+                })
+            }).collect();
+            let mut scene_nodes: Vec<usize> = roots.iter().flat_map(|root| Self::subtree_of(&nodes, *root)).collect();
+            // parents first, like in nodes
+            scene_nodes.sort_unstable();
+            Scene { name: scene.name().map(str::to_string), roots, nodes: scene_nodes }
         }).collect();
+        let scene = gltf.default_scene().map_or(0, |scene| scene.index());
+        let world_matrices = Self::world_matrices(&nodes);
+        let bounds = Self::nodes_bounds(&nodes, Self::scene_nodes_of(&scenes, scene), &world_matrices);
+        let cameras: Vec<SceneCamera> = nodes.iter().zip(world_matrices.iter())
+            .filter_map(|(node, node_matrix)| Some(SceneCamera::new(&gltf.cameras().nth(node.camera?)?, node_matrix)))
+            .collect();
+
+        let skins: Vec<Skin> = gltf.skins().map(|skin| Skin::new(&skin, &nodes, &buffer_data)).collect();
+        let node_index = |source: usize| nodes.iter().position(|node| node.source == source).map(|index| index as u32);
+        let animations: Vec<Animation> = gltf.animations().map(|animation| Animation::new(&animation, &buffer_data, node_index)).collect();
+
+        let node_bindings = NodeBindings::new(device, &nodes, &skins, &world_matrices, &morph_deltas);
 
         let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor{
             label: Some("Gltf vertex data"),
@@ -330,106 +692,443 @@ impl GLTFModel {
             //indices_cnt,
             //textures,
             materials,
-            nodes_matrices
+            node_bindings,
+            world_matrices,
+            scenes,
+            scene,
+            bounds,
+            cameras,
+            skins,
+            animations,
+            playback: Playback::default(),
+            posed: false,
+        }
+    }
+
+    // Parents come before their children in nodes
+    fn world_matrices(nodes: &[Node]) -> Vec<glm::Mat4> {
+        let mut world_matrices: Vec<glm::Mat4> = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let parent_matrix = node.parent.map_or(glm::Mat4::identity(), |parent| world_matrices[parent as usize]);
+            world_matrices.push(parent_matrix * node.transform.matrix());
+        }
+        world_matrices
+    }
+
+    fn nodes_bounds(nodes: &[Node], scene_nodes: &[usize], world_matrices: &[glm::Mat4]) -> Aabb {
+        let mut bounds = Aabb::empty();
+        for i in scene_nodes {
+            bounds.union(&nodes[*i].bounds.transform(&world_matrices[*i]));
+        }
+        bounds
+    }
+
+    // The node and its descendants, depth first
+    fn subtree_of(nodes: &[Node], node: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut stack: Vec<usize> = nodes.get(node).map(|_| node).into_iter().collect();
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(nodes[node].children().rev());
+            Some(node)
+        })
+    }
+
+    fn scene_nodes_of(scenes: &[Scene], scene: usize) -> &[usize] {
+        scenes.get(scene).map_or(&[], |scene| scene.nodes.as_slice())
+    }
+
+    fn node_skin<'s>(node: &Node, skins: &'s [Skin]) -> Option<&'s Skin> {
+        node.skin.and_then(|skin| skins.get(skin)).filter(|skin| !skin.joints.is_empty())
+    }
+
+    fn node_uniform(node: &Node, skins: &[Skin], node_matrix: &glm::Mat4, joint_offset: u32) -> NodeUniform {
+        let mut morph_weights = [[0.0; 4]; MAX_MORPH_TARGETS / 4];
+        for (i, weight) in node.morph_weights.iter().enumerate() {
+            morph_weights[i / 4][i % 4] = *weight;
+        }
+        NodeUniform {
+            transform: (*node_matrix).into(),
+            joint_count: Self::node_skin(node, skins).map_or(0, |skin| skin.joints.len() as u32),
+            morph_target_count: node.morph_weights.len() as u32,
+            joint_offset,
+            _padding: 0,
+            morph_weights,
+        }
+    }
+
+    fn joint_matrices(node: &Node, skins: &[Skin], node_matrix: &glm::Mat4, world_matrices: &[glm::Mat4]) -> Option<Vec<[[f32; 4]; 4]>> {
+        let skin = Self::node_skin(node, skins)?;
+        Some(skin.joint_matrices(node_matrix, world_matrices).into_iter().map(Into::into).collect())
+    }
+
+    // Draws nothing, what the examples show when their model fails to load
+    pub fn empty(device: &Device) -> GLTFModel {
+        // zero sized buffers can't be bound
+        let placeholder = |usage| device.create_buffer_init(&BufferInitDescriptor{
+            label: Some("Empty gltf buffer"),
+            contents: &[0u8; 4],
+            usage,
+        });
+        Self {
+            nodes: vec![],
+            vertex_buffer: placeholder(BufferUsages::VERTEX),
+            index_buffer: placeholder(BufferUsages::INDEX),
+            materials: vec![],
+            node_bindings: NodeBindings::new(device, &[], &[], &[], &[]),
+            world_matrices: vec![],
+            scenes: vec![],
+            scene: 0,
+            bounds: Aabb { min: glm::Vec3::zeros(), max: glm::Vec3::zeros() },
+            cameras: vec![],
+            skins: vec![],
+            animations: vec![],
+            playback: Playback::default(),
+            posed: false,
+        }
+    }
+
+    // Of the scene's nodes in model space, with the node transforms applied
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    // Of all scenes, in the order their nodes were loaded
+    pub fn cameras(&self) -> &[SceneCamera] {
+        &self.cameras
+    }
+
+    pub fn scenes(&self) -> &[Scene] {
+        &self.scenes
+    }
+
+    // The one drawn, the document's default scene or else its first one once the model is loaded
+    pub fn scene(&self) -> usize {
+        self.scene
+    }
+
+    // For tools picking scenes by name, the viewer only switches them by index
+    #[allow(dead_code)]
+    pub fn find_scene(&self, name: &str) -> Option<usize> {
+        self.scenes.iter().position(|scene| scene.name.as_deref() == Some(name))
+    }
+
+    pub fn set_scene(&mut self, scene: usize) {
+        if scene < self.scenes.len() {
+            self.scene = scene;
+            self.bounds = Self::nodes_bounds(&self.nodes, self.scene_nodes(), &self.world_matrices);
+        }
+    }
+
+    fn scene_nodes(&self) -> &[usize] {
+        Self::scene_nodes_of(&self.scenes, self.scene)
+    }
+
+    // Nodes of every scene, parents before their children
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    // The first node of the scene with the name
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.scene_nodes().iter().copied().find(|node| self.nodes[*node].name() == Some(name))
+    }
+
+    // The node and its descendants, depth first
+    pub fn subtree(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        Self::subtree_of(&self.nodes, node)
+    }
+
+    // In model space, as of the last update
+    pub fn world_transform(&self, node: usize) -> Option<glm::Mat4> {
+        self.world_matrices.get(node).copied()
+    }
+
+    // Of the node and its descendants in model space
+    pub fn subtree_bounds(&self, node: usize) -> Aabb {
+        let subtree: Vec<usize> = self.subtree(node).collect();
+        Self::nodes_bounds(&self.nodes, &subtree, &self.world_matrices)
+    }
+
+    pub fn animations(&self) -> &[Animation] {
+        &self.animations
+    }
+
+    // The first animation, if there's any, plays looped from the start once the model is loaded
+    pub fn playback(&self) -> Playback {
+        self.playback
+    }
+
+    // Starts the animation over
+    pub fn play(&mut self, animation: usize) {
+        self.playback = Playback { animation, time: 0.0, playing: true, ..self.playback };
+        self.posed = false;
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playback.playing = playing;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        let duration = self.animations.get(self.playback.animation).map_or(0.0, |animation| animation.duration);
+        self.playback.time = time.clamp(0.0, duration);
+        self.posed = false;
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.playback.looping = looping;
+    }
+
+    // Nodes with morph targets and their weights, indexed like the model's nodes
+    pub fn morph_weights(&self) -> impl Iterator<Item = (usize, &[f32])> {
+        self.nodes.iter().enumerate()
+            .filter(|(_, node)| !node.morph_weights.is_empty())
+            .map(|(i, node)| (i, node.morph_weights.as_slice()))
+    }
+
+    // Animations targeting the node's weights overwrite them
+    pub fn set_morph_weights(&mut self, node: usize, weights: &[f32]) {
+        if let Some(node) = self.nodes.get_mut(node) {
+            node.morph_weights.iter_mut().zip(weights).for_each(|(weight, value)| *weight = *value);
+            self.posed = false;
+        }
+    }
+
+    // Advances the playing animation and moves the nodes, their skins and the model's bounds along with it
+    pub fn update(&mut self, queue: &Queue, delta: f32) {
+        if let Some(animation) = self.animations.get(self.playback.animation) {
+            if self.playback.playing || !self.posed {
+                self.playback.advance(delta, animation.duration);
+                animation.apply(self.playback.time, &mut self.nodes);
+                self.posed = false;
+            }
+        }
+        if self.posed {
+            return;
         }
+        self.posed = true;
+
+        self.world_matrices = Self::world_matrices(&self.nodes);
+        self.bounds = Self::nodes_bounds(&self.nodes, self.scene_nodes(), &self.world_matrices);
+        self.node_bindings.write(queue, &self.nodes, &self.skins, &self.world_matrices);
+    }
+
+    // Every topology the model's primitives use, each one needs its own pipeline
+    pub fn topologies(&self) -> Vec<PrimitiveTopology> {
+        let mut topologies = Vec::new();
+        for primitive in self.nodes.iter().flat_map(|node| node.mesh.primitives.iter()) {
+            if !topologies.contains(&primitive.topology) {
+                topologies.push(primitive.topology);
+            }
+        }
+        topologies
     }
 
-    fn load_node(model: &Gltf, input_node: &gltf::Node, gltf: &Gltf, parent: Option<u32>, index_buffer: &mut Vec<u32>,  vertex_buffer: &mut Vec<Vertex>, buffer_data: &Vec<Vec<u8>>, nodes: &mut Vec<Node>) {
+    fn load_node(default_material: u32, input_node: &gltf::Node, gltf: &Gltf, parent: Option<u32>, index_buffer: &mut Vec<u32>,  vertex_buffer: &mut Vec<Vertex>, morph_deltas: &mut Vec<MorphDelta>, buffer_data: &Vec<Vec<u8>>, nodes: &mut Vec<Node>) {
         let mut cur_node = Node::default();
 		cur_node.parent = parent;
+        cur_node.name = input_node.name().map(str::to_string);
 
         let cur_node_index = nodes.len() as u32;
         nodes.push(cur_node);
 
-        // FIXME flatten()
-        nodes[cur_node_index as usize].matrix = mat4(
-            input_node.transform().matrix()[0][0], input_node.transform().matrix()[1][0], input_node.transform().matrix()[2][0], input_node.transform().matrix()[3][0],
-            input_node.transform().matrix()[0][1], input_node.transform().matrix()[1][1], input_node.transform().matrix()[2][1], input_node.transform().matrix()[3][1],
-            input_node.transform().matrix()[0][2], input_node.transform().matrix()[1][2], input_node.transform().matrix()[2][2], input_node.transform().matrix()[3][2],
-            input_node.transform().matrix()[0][3], input_node.transform().matrix()[1][3], input_node.transform().matrix()[2][3], input_node.transform().matrix()[3][3],
-        );
+        // nodes given as a matrix get decomposed too, animations only ever target TRS nodes
+        let (translation, [x, y, z, w], scale) = input_node.transform().decomposed();
+        nodes[cur_node_index as usize].transform = NodeTransform {
+            translation: glm::make_vec3(&translation),
+            rotation: glm::quat(x, y, z, w),
+            scale: glm::make_vec3(&scale),
+        };
 
-        // cur_node.translation = glm::make_vec3(&input_node.transform().decomposed().0);
-        // cur_node.rotation = glm::make_quat(&input_node.transform().decomposed().1);
-        // cur_node.scale = glm::make_vec3(&input_node.transform().decomposed().2);
+        nodes[cur_node_index as usize].camera = input_node.camera().map(|camera| camera.index());
+        nodes[cur_node_index as usize].skin = input_node.skin().map(|skin| skin.index());
+        nodes[cur_node_index as usize].source = input_node.index();
 
         for child in input_node.children() {
-            Self::load_node(model, &child, gltf, Some(cur_node_index), index_buffer, vertex_buffer, buffer_data, nodes);
+            let child_index = nodes.len() as u32;
+            nodes[cur_node_index as usize].children.push(child_index);
+            Self::load_node(default_material, &child, gltf, Some(cur_node_index), index_buffer, vertex_buffer, morph_deltas, buffer_data, nodes);
         }
 
         if let Some(mesh) = input_node.mesh() {
+            // every primitive of a mesh has the same targets
+            let target_count = mesh.primitives().next().map_or(0, |primitive| primitive.morph_targets().len());
+            if target_count > MAX_MORPH_TARGETS {
+                println!("Mesh {} has {} morph targets, only the first {} are blended", mesh.name().unwrap_or("unnamed"), target_count, MAX_MORPH_TARGETS);
+            }
+            let node_weights = input_node.weights().or_else(|| mesh.weights()).unwrap_or(&[]);
+            nodes[cur_node_index as usize].morph_weights = (0..target_count.min(MAX_MORPH_TARGETS)).map(|i| node_weights.get(i).copied().unwrap_or(0.0)).collect();
+
             for primitive in mesh.primitives() {
                 let first_index: u32 = index_buffer.len() as u32;
                 let vertex_start: u32 = vertex_buffer.len() as u32;
-                let mut index_count = 0u32;
+                let mut bounds = Aabb::empty();
             
                 // vertices
-                {
-                    let reader = primitive.reader(|buffer| Some(&buffer_data[buffer.index()]));
+                let topology = {
+                    let reader = primitive.reader(|buffer| buffer_data.get(buffer.index()).map(Vec::as_slice));
                     if let Some(iter) = reader.read_positions() {
+                        // attributes shorter than the positions leave the remaining vertices with defaults
+                        let uv0: Vec<[f32; 2]> = reader.read_tex_coords(0).map_or(vec![], |iter| iter.into_f32().collect());
+                        let uv1: Vec<[f32; 2]> = reader.read_tex_coords(1).map_or(vec![], |iter| iter.into_f32().collect());
+                        let normals: Vec<[f32; 3]> = reader.read_normals().map_or(vec![], |iter| iter.collect());
+                        let colors: Vec<[f32; 3]> = reader.read_colors(0).map_or(vec![], |iter| iter.into_rgb_f32().collect());
+                        let tangents: Vec<[f32; 4]> = reader.read_tangents().map_or(vec![], |iter| iter.collect());
+                        let joints: Vec<[u16; 4]> = reader.read_joints(0).map_or(vec![], |iter| iter.into_u16().collect());
+                        let weights: Vec<[f32; 4]> = reader.read_weights(0).map_or(vec![], |iter| iter.into_f32().collect());
                         for (i, pos) in iter.enumerate() {
-                            let mut vertex = Vertex::default();
-                            vertex.pos = pos.into();
-                            
-                            if let Some(iter) = reader.read_tex_coords(0) {
-                                vertex.uv0 = iter.into_f32().nth(i).unwrap().into();
-                            }
-                            if let Some(iter) = reader.read_tex_coords(1) {
-                                vertex.uv1 = iter.into_f32().nth(i).unwrap().into();
-                            }
-                            if let Some(mut iter) = reader.read_normals() {
-                                vertex.normal = iter.nth(i).unwrap().into();
-                            }
-                            if let Some(iter) = reader.read_colors(0) {
-                                vertex.color = iter.into_rgb_f32().nth(i).unwrap().into();
-                            } else {
-                                vertex.color = [1.0; 3];
+                            bounds.extend(&pos.into());
+                            vertex_buffer.push(Vertex {
+                                pos,
+                                normal: normals.get(i).copied().unwrap_or_default(),
+                                uv0: uv0.get(i).copied().unwrap_or_default(),
+                                uv1: uv1.get(i).copied().unwrap_or_default(),
+                                color: colors.get(i).copied().unwrap_or([1.0; 3]),
+                                tangent: tangents.get(i).copied().unwrap_or_default(),
+                                joints: joints.get(i).map_or([0; 4], |joints| joints.map(u32::from)),
+                                weights: weights.get(i).copied().unwrap_or_default(),
+                                morph_index: 0,
+                            });
+                        }
+
+                        let targets: Vec<[Vec<[f32; 3]>; 3]> = reader.read_morph_targets().take(MAX_MORPH_TARGETS).map(|(positions, normals, tangents)| [
+                            positions.map_or(vec![], Iterator::collect),
+                            normals.map_or(vec![], Iterator::collect),
+                            tangents.map_or(vec![], Iterator::collect),
+                        ]).collect();
+                        if !targets.is_empty() {
+                            for (i, vertex) in vertex_buffer[vertex_start as usize..].iter_mut().enumerate() {
+                                vertex.morph_index = morph_deltas.len() as u32;
+                                for [positions, normals, tangents] in &targets {
+                                    let delta = |deltas: &Vec<[f32; 3]>| {
+                                        let [x, y, z] = deltas.get(i).copied().unwrap_or_default();
+                                        [x, y, z, 0.0]
+                                    };
+                                    morph_deltas.push(MorphDelta { position: delta(positions), normal: delta(normals), tangent: delta(tangents) });
+                                    // roomy enough for weights up to 1
+                                    bounds.extend(&(glm::make_vec3(&vertex.pos) + glm::make_vec3(&delta(positions)[..3])));
+                                }
                             }
-                            vertex_buffer.push(vertex);
                         }
                     }
                     
-                    if let Some(iter) = reader.read_indices() {
-                        for index in iter.into_u32() {
-                            index_buffer.push(index + vertex_start);
-                            index_count += 1;
+                    // non-indexed primitives draw their vertices in order
+                    let vertex_count = vertex_buffer.len() as u32 - vertex_start;
+                    let indices = reader.read_indices().map_or_else(|| (0..vertex_count).collect(), |iter| iter.into_u32().collect());
+                    let (topology, mut indices) = list_indices(primitive.mode(), indices);
+
+                    // MikkTSpace tangents for normal mapped triangles that come without them, as the spec asks for
+                    let needs_tangents = reader.read_tangents().is_none() && reader.read_normals().is_some() && topology == PrimitiveTopology::TriangleList;
+                    let normal_uv_set = primitive.material().normal_texture().map(|texture| texture.tex_coord()).filter(|uv_set| reader.read_tex_coords(*uv_set).is_some());
+                    if let Some(uv_set) = normal_uv_set.filter(|_| needs_tangents) {
+                        match generate_tangents(&vertex_buffer[vertex_start as usize..], &indices, uv_set) {
+                            Some((vertices, welded_indices)) => {
+                                vertex_buffer.truncate(vertex_start as usize);
+                                vertex_buffer.extend(vertices);
+                                indices = welded_indices;
+                            },
+                            None => println!("Failed to generate tangents of a {} primitive", mesh.name().unwrap_or("unnamed")),
                         }
                     }
-                }
+                    index_buffer.extend(indices.into_iter().map(|index| index + vertex_start));
+                    topology
+                };
 
                 let primitive = Primitive{
                     first_index,
-                    index_count,
-                    material_index: primitive.material().index().unwrap() as u32,
+                    index_count: index_buffer.len() as u32 - first_index,
+                    material_index: primitive.material().index().map_or(default_material, |index| index as u32),
+                    topology,
+                    bounds,
                 };
+                nodes[cur_node_index as usize].bounds.union(&bounds);
                 nodes[cur_node_index as usize].mesh.primitives.push(primitive);
             }
         }
     }
 }
 
+// Unrolls a glTF primitive's indices into a list wgpu can draw without strip index formats or primitive restart
+fn list_indices(mode: gltf::mesh::Mode, indices: Vec<u32>) -> (PrimitiveTopology, Vec<u32>) {
+    use gltf::mesh::Mode;
+    let count = indices.len();
+    match mode {
+        Mode::Points => (PrimitiveTopology::PointList, indices),
+        Mode::Lines => (PrimitiveTopology::LineList, indices),
+        Mode::LineStrip | Mode::LineLoop => {
+            let mut lines: Vec<u32> = indices.windows(2).flatten().copied().collect();
+            if mode == Mode::LineLoop && count > 2 {
+                lines.extend([indices[count - 1], indices[0]]);
+            }
+            (PrimitiveTopology::LineList, lines)
+        },
+        Mode::Triangles => (PrimitiveTopology::TriangleList, indices),
+        // every other triangle of a strip is flipped to keep the winding
+        Mode::TriangleStrip => (PrimitiveTopology::TriangleList, (0..count.saturating_sub(2)).flat_map(|i| {
+            let odd = i % 2;
+            [indices[i], indices[i + 1 + odd], indices[i + 2 - odd]]
+        }).collect()),
+        Mode::TriangleFan => (PrimitiveTopology::TriangleList, (1..count.saturating_sub(1)).flat_map(|i| {
+            [indices[i], indices[i + 1], indices[0]]
+        }).collect()),
+    }
+}
+
+// The pipelines a model gets drawn with, one per topology of its primitives. They are expected to
+// differ only in PrimitiveState::topology.
+pub struct ModelPipelines {
+    pipelines: Vec<(PrimitiveTopology, RenderPipeline)>,
+}
+
+impl ModelPipelines {
+    pub fn new(model: &GLTFModel, create_pipeline: impl Fn(PrimitiveTopology) -> RenderPipeline) -> Self {
+        Self { pipelines: model.topologies().into_iter().map(|topology| (topology, create_pipeline(topology))).collect() }
+    }
+}
+
 pub(crate) trait Drawable<'a> {
-    fn draw_model(&mut self, model: &'a GLTFModel, mode_mm_bg_index: u32);
-    fn draw_node(&mut self, node: &Node, model: &'a GLTFModel);
+    // Sets each topology's pipeline in turn and skips the primitives outside the frustum
+    fn draw_model(&mut self, model: &'a GLTFModel, pipelines: &'a ModelPipelines, mode_mm_bg_index: u32, frustum: &Frustum) -> DrawStats;
+    fn draw_node(&mut self, node: &Node, model: &'a GLTFModel, topology: PrimitiveTopology, world_matrix: &glm::Mat4, frustum: &Frustum, stats: &mut DrawStats);
 }
 
 impl<'a, 'b> Drawable<'b> for wgpu::RenderPass<'a> where 'b: 'a, {
-    fn draw_model(&mut self, model: &'a GLTFModel, mode_mm_bg_index: u32) {
+    fn draw_model(&mut self, model: &'a GLTFModel, pipelines: &'a ModelPipelines, mode_mm_bg_index: u32, frustum: &Frustum) -> DrawStats {
         self.set_vertex_buffer(0, model.vertex_buffer.slice(..));
         self.set_index_buffer(model.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        let mut stats = DrawStats::default();
         let nodes = &model.nodes;
-        //for (i, node) in nodes.iter().enumerate() {
-        for (i, node) in nodes.iter().enumerate() {
-            self.set_bind_group(mode_mm_bg_index, &model.nodes_matrices[i], &[]);
-            self.draw_node(&node, &model);
+        for (topology, pipeline) in &pipelines.pipelines {
+            self.set_pipeline(pipeline);
+            //for (i, node) in nodes.iter().enumerate() {
+            for &i in model.scene_nodes() {
+                let node = &nodes[i];
+                let primitive_count = node.mesh.primitives.iter().filter(|primitive| primitive.topology == *topology).count() as u32;
+                if primitive_count == 0 {
+                    continue;
+                }
+                // the whole node first, its primitives only need testing when it's partly visible.
+                // Skinned vertices go wherever their joints are, so skinned nodes are never culled.
+                if node.skin.is_none() && !frustum.intersects(&node.bounds.transform(&model.world_matrices[i])) {
+                    stats.culled += primitive_count;
+                    continue;
+                }
+                self.set_bind_group(mode_mm_bg_index, &model.node_bindings.bind_group, &[i as u32 * model.node_bindings.node_stride]);
+                self.draw_node(&node, &model, *topology, &model.world_matrices[i], frustum, &mut stats);
+            }
         }
+        stats
     }
 
-    fn draw_node(&mut self, node: &Node, model: &'a GLTFModel) {
+    fn draw_node(&mut self, node: &Node, model: &'a GLTFModel, topology: PrimitiveTopology, world_matrix: &glm::Mat4, frustum: &Frustum, stats: &mut DrawStats) {
         if node.mesh.primitives.len() > 0 {
             let mesh = node.mesh.borrow();
             let primives: &Vec<Primitive> = &mesh.primitives;
-            for primitive in primives.iter()/*.filter(|p| model.materials[p.material_index as usize].alpha_mode == AlphaMode::Blend)*/ {
+            for primitive in primives.iter().filter(|primitive| primitive.topology == topology)/*.filter(|p| model.materials[p.material_index as usize].alpha_mode == AlphaMode::Blend)*/ {
+                if node.skin.is_none() && !frustum.intersects(&primitive.bounds.transform(world_matrix)) {
+                    stats.culled += 1;
+                    continue;
+                }
+                stats.visible += 1;
                 if primitive.index_count > 0 {
                     // TODO
                     //let tex_index = model.materials[primitive.material_index as usize].base_color_texture_index as usize;
@@ -444,12 +1143,267 @@ impl<'a, 'b> Drawable<'b> for wgpu::RenderPass<'a> where 'b: 'a, {
     }
 }
 
-pub async fn parse_gltf<T: ResourceManager>(file_name: &str, device: &wgpu::Device, queue: &Queue, resource_manager: &T) -> GLTFModel {
+impl Material {
+    fn from_gltf(cur_material: &gltf::Material) -> Self {
+        let mut material = Material::default();
+        material.double_sided = cur_material.double_sided();
+        (material.base_color_texture_index,
+         material.tex_coord_sets.base_color)  = match cur_material.pbr_metallic_roughness().base_color_texture() {
+            Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
+            None => (None, u8::MAX),
+        };
+        (material.metallic_roughness_texture_index,
+         material.tex_coord_sets.metallic_roughness) = match cur_material.pbr_metallic_roughness().metallic_roughness_texture() {
+            Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
+            None => (None, u8::MAX),
+        };
+        material.roughness_factor = cur_material.pbr_metallic_roughness().roughness_factor();
+        material.metallic_factor = cur_material.pbr_metallic_roughness().metallic_factor();
+        material.base_color_factor = cur_material.pbr_metallic_roughness().base_color_factor();
+
+        (material.normal_texture_index,
+         material.tex_coord_sets.normal) = match cur_material.normal_texture() {
+            Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
+            None => (None, u8::MAX),
+        };
+        (material.emissive_texture_index,
+            material.tex_coord_sets.emissive) = match cur_material.emissive_texture() {
+               Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
+               None => (None, u8::MAX),
+           };
+
+        (material.occlusion_texture_index,
+            material.tex_coord_sets.occlusion) = match cur_material.occlusion_texture() {
+               Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
+               None => (None, u8::MAX),
+           };
+        
+        material.alpha_mode = cur_material.alpha_mode();
+        material.alpha_cutoff = match cur_material.alpha_cutoff() {
+            Some(val) => val,
+            None => 0.5,
+        };
+        material.emissive_factor = [cur_material.emissive_factor()[0], cur_material.emissive_factor()[1], cur_material.emissive_factor()[2], 1.0];
+
+        material
+    }
+
+    // What primitives without a material get, as the spec defines it: opaque white, fully metallic and rough
+    fn gltf_default() -> Self {
+        Self {
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            base_color_factor: [1.0; 4],
+            emissive_factor: [0.0, 0.0, 0.0, 1.0],
+            alpha_cutoff: 0.5,
+            tex_coord_sets: TexCoordSets { base_color: u8::MAX, metallic_roughness: u8::MAX, specular_glossiness: u8::MAX,
+                                           normal: u8::MAX, occlusion: u8::MAX, emissive: u8::MAX },
+            ..Default::default()
+        }
+    }
+}
+
+// Material uniforms plus its textures, missing or broken ones are replaced with the empty texture
+fn create_material_bind_group(device: &Device, material_bgl: &wgpu::BindGroupLayout, material: &Material, name: &str,
+                              textures: &[Option<(TextureView, Sampler)>], empty_tex: &TextureView, default_sampler: &Sampler) -> BindGroup {
+    let texture = |index: Option<u32>| index.and_then(|index| textures.get(index as usize)?.as_ref())
+        .map_or((empty_tex, default_sampler), |(view, sampler)| (view, sampler));
+    let m = Mat {
+        base_color_factor: material.base_color_factor,
+        base_color_texture_set: match material.base_color_texture_index {
+            Some(index) => index as i32,
+            None => -1,
+        },
+        physical_descriptor_texture_set: match material.metallic_roughness_texture_index {
+            Some(index) => index as i32,
+            None => -1,
+        },
+        normal_texture_set: match material.normal_texture_index {
+            Some(index) => index as i32,
+            None => -1,
+        },
+        occlusion_texture_set: match material.occlusion_texture_index {
+            Some(index) => index as i32,
+            None => -1,
+        },
+        emissive_texture_set: match material.emissive_texture_index {
+            Some(index) => index as i32,
+            None => -1,
+        },
+        metallic_factor: material.metallic_factor,
+        roughness_factor: material.roughness_factor,
+        alpha_mask: 1.0,
+        alpha_mask_cutoff: material.alpha_cutoff,
+        alignment: [0.0f32; 3],
+    };
+    // let m = Mat {
+    //     base_color_factor: [1.0f32; 4],
+    //     base_color_texture_set: 0,
+    //     physical_descriptor_texture_set: 0,
+    //     normal_texture_set: 0,
+    //     occlusion_texture_set: 0,
+    //     emissive_texture_set: 0,
+    //     metallic_factor: 1.0f32,
+    //     roughness_factor: 1.0f32,
+    //     alpha_mask: 0.0f32,
+    //     alpha_mask_cutoff: 0.5f32,
+    //     alignment: [0.0f32; 3],
+    // };
+    let buff = device.create_buffer_init(&BufferInitDescriptor{
+        label: Some(format!("material {} buffer", name).as_str()),
+        contents: bytemuck::cast_slice(&[m]),
+        usage: BufferUsages::UNIFORM,
+    });
+
+    device.create_bind_group(&BindGroupDescriptor{
+        label: Some(format!("Material {} BG", name).as_str()),
+        layout: &material_bgl,
+        entries: &[
+            // base color
+            BindGroupEntry{
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    texture(material.base_color_texture_index).0
+                    //&textures[material.base_color_texture_index.unwrap() as usize].as_ref().unwrap().0
+                )
+            },
+            BindGroupEntry{
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(
+                    texture(material.base_color_texture_index).1
+                    //&textures[material.base_color_texture_index.unwrap() as usize].as_ref().unwrap().1
+                )
+            },
+
+            // metallic roughness
+            BindGroupEntry{
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(
+                    texture(material.metallic_roughness_texture_index).0
+                )
+            },
+            BindGroupEntry{
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(
+                    texture(material.metallic_roughness_texture_index).1
+                )
+            },
+
+            // normal 
+            BindGroupEntry{
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(
+                    texture(material.normal_texture_index).0
+                )
+            },
+            BindGroupEntry{
+                binding: 5,
+                resource: wgpu::BindingResource::Sampler(
+                    texture(material.normal_texture_index).1
+                )
+            },
+
+            // occlusion 
+            BindGroupEntry{
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(
+                    texture(material.occlusion_texture_index).0
+                )
+            },
+            BindGroupEntry{
+                binding: 7,
+                resource: wgpu::BindingResource::Sampler(
+                    texture(material.occlusion_texture_index).1
+                )
+            },
+
+            // emissive 
+            BindGroupEntry{
+                binding: 8,
+                resource: wgpu::BindingResource::TextureView(
+                    texture(material.emissive_texture_index).0
+                )
+            },
+            BindGroupEntry{
+                binding: 9,
+                resource: wgpu::BindingResource::Sampler(
+                    texture(material.emissive_texture_index).1
+                )
+            },
+            BindGroupEntry{
+                binding: 10,
+                resource: buff.as_entire_binding()
+            },
+        ],
+    })
+}
+
+// Resolves a buffer or image uri, either embedded data or a path relative to the gltf file
+fn load_uri<T: ResourceManager>(uri: &str, file_name: &str, resource_manager: &T) -> anyhow::Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        return resource_manager.load_base64(uri).with_context(|| format!("Bad data uri {:.32}...", uri));
+    }
+    let path = Path::new(file_name).parent().unwrap_or(Path::new("")).join(decode_uri(uri));
+    let path = path.to_str().with_context(|| format!("Bad uri {}", uri))?;
+    resource_manager.load_binary(path).with_context(|| format!("Failed to load {}", path))
+}
+
+// Relative uris may escape characters like spaces as %20
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn load_image<T: ResourceManager>(image: &gltf::Image, file_name: &str, resource_manager: &T, buffer_data: &[Vec<u8>]) -> anyhow::Result<image::DynamicImage> {
+    let data = match image.source() {
+        gltf::image::Source::Uri { uri, .. } => load_uri(uri, file_name, resource_manager)?,
+        // .glb files usually keep their images in the binary chunk
+        gltf::image::Source::View { view, .. } => {
+            let buffer = buffer_data.get(view.buffer().index()).with_context(|| format!("Image {} refers to a missing buffer", image.index()))?;
+            buffer.get(view.offset()..view.offset() + view.length())
+                .with_context(|| format!("Buffer view of image {} is out of bounds", image.index()))?
+                .to_vec()
+        },
+    };
+    image::load_from_memory(&data).with_context(|| format!("Failed to decode image {}", image.index()))
+}
+
+pub async fn parse_gltf<T: ResourceManager>(file_name: &str, device: &wgpu::Device, queue: &Queue, resource_manager: &T) -> anyhow::Result<GLTFModel> {
     let empty_tex = resource_manager.empty_tex(device, queue);
-    let gltf_text = resource_manager.load_string(file_name).ok().unwrap();
-    let gltf_cursor = Cursor::new(gltf_text);
-    let gltf_reader = BufReader::new(gltf_cursor);
-    let gltf = Gltf::from_reader(gltf_reader).ok().unwrap();
+    // json .gltf as well as binary .glb
+    let gltf_data = resource_manager.load_binary(file_name).with_context(|| format!("Failed to read {}", file_name))?;
+    let mut gltf = Gltf::from_slice(&gltf_data).with_context(|| format!("Failed to parse {}", file_name))?;
+
+    // buffers go first, images may live in them
+    let mut blob = gltf.blob.take();
+    let mut buffer_data = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => load_uri(uri, file_name, resource_manager).with_context(|| format!("Failed to load buffer {}", buffer.index()))?,
+            gltf::buffer::Source::Bin => blob.take().with_context(|| format!("Buffer {} refers to a missing BIN chunk", buffer.index()))?,
+        };
+        if data.len() < buffer.length() {
+            bail!("Buffer {} has {} bytes, {} expected", buffer.index(), data.len(), buffer.length());
+        }
+        buffer_data.push(data);
+    }
     //let textures: Vec<Option<Texture>> = gltf.textures().map(|cur_tex| {
     // let mut samplers: Vec<Sampler> = gltf.samplers().map(|cur_sampler| {
     //     device.create_sampler(&SamplerDescriptor{
@@ -492,30 +1446,22 @@ pub async fn parse_gltf<T: ResourceManager>(file_name: &str, device: &wgpu::Devi
     // }).collect();
     //let default_sampler = device.create_sampler(&SamplerDescriptor::default());
 
-    // TODO no need for opt
+    // textures that fail to load are None and the materials use the empty texture instead
     let textures: Vec<Option<(TextureView, Sampler)>> = gltf.textures().map(|cur_tex| {
-        let cur_image = match cur_tex.source().source() {
-            gltf::image::Source::Uri { uri, .. } => {
-                let data = if uri.starts_with("data:") {
-                    Some(resource_manager.load_base64(uri).unwrap())
-                } else {
-                    let mut path_buf = PathBuf::from(file_name);
-                    if let Some(parent) = path_buf.parent() {
-                        let new_uri = parent.join(uri);
-                        Some(resource_manager.load_binary(&new_uri.into_os_string().into_string().unwrap()).unwrap())
-                    } else {
-                        None
-                    }
-                };
-
-                image::load_from_memory(&data.unwrap()).unwrap()
-            },
-            _ => panic!("AAAAAAAAAAAAAA")
+        let cur_image = match load_image(&cur_tex.source(), file_name, resource_manager, &buffer_data) {
+            Ok(image) => image,
+            Err(error) => {
+                println!("Texture {} of {} falls back to an empty one - {:?}", cur_tex.index(), file_name, error);
+                return None;
+            }
         };
 
         let cur_rgba = cur_image.to_rgba8();
         let (cur_width, cur_height) = cur_image.dimensions();
 
+        let label = cur_tex.name().or_else(|| cur_tex.source().name())
+            .map_or_else(|| format!("{} texture {}", file_name, cur_tex.index()), str::to_string);
+
         let cur_size = wgpu::Extent3d {
             width: cur_width,
             height: cur_height,
@@ -529,7 +1475,7 @@ pub async fn parse_gltf<T: ResourceManager>(file_name: &str, device: &wgpu::Devi
                 dimension: wgpu::TextureDimension::D2,
                 format: wgpu::TextureFormat::Rgba8Unorm,
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                label: Some("font atlas texture"),
+                label: Some(&label),
                 view_formats: &[],
             }
         );
@@ -639,230 +1585,17 @@ pub async fn parse_gltf<T: ResourceManager>(file_name: &str, device: &wgpu::Devi
     // };
     let default_sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
     let material_bgl = &device.create_bind_group_layout(&MATERIAL_BGL);
-    let materials = gltf.materials().map(|cur_material| {
-        let mut material = Material::default();
-        material.double_sided = cur_material.double_sided();
-        (material.base_color_texture_index,
-         material.tex_coord_sets.base_color)  = match cur_material.pbr_metallic_roughness().base_color_texture() {
-            Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
-            None => (None, u8::MAX),
-        };
-        (material.metallic_roughness_texture_index,
-         material.tex_coord_sets.metallic_roughness) = match cur_material.pbr_metallic_roughness().metallic_roughness_texture() {
-            Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
-            None => (None, u8::MAX),
-        };
-        material.roughness_factor = cur_material.pbr_metallic_roughness().roughness_factor();
-        material.metallic_factor = cur_material.pbr_metallic_roughness().metallic_factor();
-        material.base_color_factor = cur_material.pbr_metallic_roughness().base_color_factor();
-
-        (material.normal_texture_index,
-         material.tex_coord_sets.normal) = match cur_material.normal_texture() {
-            Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
-            None => (None, u8::MAX),
-        };
-        (material.emissive_texture_index,
-            material.tex_coord_sets.emissive) = match cur_material.emissive_texture() {
-               Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
-               None => (None, u8::MAX),
-           };
-
-        (material.occlusion_texture_index,
-            material.tex_coord_sets.occlusion) = match cur_material.occlusion_texture() {
-               Some(tex) => (Some(tex.texture().index() as u32), tex.tex_coord() as u8),
-               None => (None, u8::MAX),
-           };
-        
-        material.alpha_mode = cur_material.alpha_mode();
-        material.alpha_cutoff = match cur_material.alpha_cutoff() {
-            Some(val) => val,
-            None => 0.5,
-        };
-        material.emissive_factor = [cur_material.emissive_factor()[0], cur_material.emissive_factor()[1], cur_material.emissive_factor()[2], 1.0];
-
-        let m = Mat {
-            base_color_factor: material.base_color_factor,
-            base_color_texture_set: match material.base_color_texture_index {
-                Some(index) => index as i32,
-                None => -1,
-            },
-            physical_descriptor_texture_set: match material.metallic_roughness_texture_index {
-                Some(index) => index as i32,
-                None => -1,
-            },
-            normal_texture_set: match material.normal_texture_index {
-                Some(index) => index as i32,
-                None => -1,
-            },
-            occlusion_texture_set: match material.occlusion_texture_index {
-                Some(index) => index as i32,
-                None => -1,
-            },
-            emissive_texture_set: match material.emissive_texture_index {
-                Some(index) => index as i32,
-                None => -1,
-            },
-            metallic_factor: material.metallic_factor,
-            roughness_factor: material.roughness_factor,
-            alpha_mask: 1.0,
-            alpha_mask_cutoff: material.alpha_cutoff,
-            alignment: [0.0f32; 3],
-        };
-        // let m = Mat {
-        //     base_color_factor: [1.0f32; 4],
-        //     base_color_texture_set: 0,
-        //     physical_descriptor_texture_set: 0,
-        //     normal_texture_set: 0,
-        //     occlusion_texture_set: 0,
-        //     emissive_texture_set: 0,
-        //     metallic_factor: 1.0f32,
-        //     roughness_factor: 1.0f32,
-        //     alpha_mask: 0.0f32,
-        //     alpha_mask_cutoff: 0.5f32,
-        //     alignment: [0.0f32; 3],
-        // };
-        let buff = device.create_buffer_init(&BufferInitDescriptor{
-            label: Some(format!("material {} buffer", cur_material.name().unwrap_or("unnamed")).as_str()),
-            contents: bytemuck::cast_slice(&[m]),
-            usage: BufferUsages::UNIFORM,
-        });
-
-        material.bind_group = Some(device.create_bind_group(&BindGroupDescriptor{
-            label: Some(format!("Material {} BG", cur_material.name().unwrap_or("unnamed")).as_str()),
-            layout: &material_bgl,
-            entries: &[
-                // base color
-                BindGroupEntry{
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        match material.base_color_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().0,
-                            None => &empty_tex,
-                        }
-                        //&textures[material.base_color_texture_index.unwrap() as usize].as_ref().unwrap().0
-                    )
-                },
-                BindGroupEntry{
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(
-                        match material.base_color_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().1,
-                            None => &default_sampler,
-                        }
-                        //&textures[material.base_color_texture_index.unwrap() as usize].as_ref().unwrap().1
-                    )
-                },
-
-                // metallic roughness
-                BindGroupEntry{
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(
-                        match material.metallic_roughness_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().0,
-                            None => &empty_tex,
-                        }
-                    )
-                },
-                BindGroupEntry{
-                    binding: 3,
-                    resource: wgpu::BindingResource::Sampler(
-                        match material.metallic_roughness_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().1,
-                            None => &default_sampler,
-                        }
-                    )
-                },
-
-                // normal 
-                BindGroupEntry{
-                    binding: 4,
-                    resource: wgpu::BindingResource::TextureView(
-                        match material.normal_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().0,
-                            None => &empty_tex,
-                        }
-                    )
-                },
-                BindGroupEntry{
-                    binding: 5,
-                    resource: wgpu::BindingResource::Sampler(
-                        match material.normal_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().1,
-                            None => &default_sampler,
-                        }
-                    )
-                },
-
-                // occlusion 
-                BindGroupEntry{
-                    binding: 6,
-                    resource: wgpu::BindingResource::TextureView(
-                        match material.occlusion_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().0,
-                            None => &empty_tex,
-                        }
-                    )
-                },
-                BindGroupEntry{
-                    binding: 7,
-                    resource: wgpu::BindingResource::Sampler(
-                        match material.occlusion_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().1,
-                            None => &default_sampler,
-                        }
-                    )
-                },
-
-                // emissive 
-                BindGroupEntry{
-                    binding: 8,
-                    resource: wgpu::BindingResource::TextureView(
-                        match material.emissive_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().0,
-                            None => &empty_tex
-                        }
-                    )
-                },
-                BindGroupEntry{
-                    binding: 9,
-                    resource: wgpu::BindingResource::Sampler(
-                        match material.emissive_texture_index {
-                            Some(ind) => &textures[ind as usize].as_ref().unwrap().1,
-                            None => &default_sampler,
-                        }
-                    )
-                },
-                BindGroupEntry{
-                    binding: 10,
-                    resource: buff.as_entire_binding()
-                },
-            ],
-        }));
-
+    let mut materials: Vec<Material> = gltf.materials().map(|cur_material| {
+        let mut material = Material::from_gltf(&cur_material);
+        material.bind_group = Some(create_material_bind_group(device, material_bgl, &material, cur_material.name().unwrap_or("unnamed"),
+                                                              &textures, &empty_tex, &default_sampler));
         material
     }).collect();
-    let mut buffer_data = Vec::new();
-    for buffer in gltf.buffers() {
-        match buffer.source() {
-            gltf::buffer::Source::Uri(uri) => {
-                let data = if uri.starts_with("data:") {
-                    Some(resource_manager.load_base64(uri).unwrap())
-                } else {
-                    let mut path_buf = PathBuf::from(file_name);
-                    if let Some(parent) = path_buf.parent() {
-                        let new_uri = parent.join(uri);
-                        Some(resource_manager.load_binary(&new_uri.into_os_string().into_string().unwrap()).unwrap())
-                    } else {
-                        None
-                    }
-                };
-                buffer_data.push(data.unwrap());
-            },
-            _ => panic!("AAAAAAA")
-        }
-    }
-
-    GLTFModel::new(device, gltf, materials, /*textures, */buffer_data)
+    let mut default_material = Material::gltf_default();
+    default_material.bind_group = Some(create_material_bind_group(device, material_bgl, &default_material, "default",
+                                                                  &textures, &empty_tex, &default_sampler));
+    materials.push(default_material);
+    Ok(GLTFModel::new(device, gltf, materials, /*textures, */buffer_data))
 }
 
 #[repr(C)]
@@ -879,4 +1612,31 @@ struct Mat {
 	alpha_mask:                             f32,
 	alpha_mask_cutoff:                      f32,
     alignment:                              [f32; 3]
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrored_uv_seam_splits_tangents() {
+        // two quads side by side, u runs up to the shared column and back down on the mirrored side
+        let vertex = |x: f32, y: f32, u: f32| Vertex { pos: [x, y, 0.0], normal: [0.0, 0.0, 1.0], uv0: [u, y], ..Vertex::default() };
+        let vertices = [vertex(0.0, 0.0, 0.0), vertex(1.0, 0.0, 1.0), vertex(2.0, 0.0, 0.0),
+                        vertex(0.0, 1.0, 0.0), vertex(1.0, 1.0, 1.0), vertex(2.0, 1.0, 0.0)];
+        let indices = [0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4];
+        let (vertices, indices) = generate_tangents(&vertices, &indices, 0).unwrap();
+
+        // the seam column is split, the rest stays welded
+        assert_eq!(vertices.len(), 8);
+        let tangent = |corner: usize| vertices[indices[corner] as usize].tangent;
+        for corner in 0..6 {
+            assert_eq!(tangent(corner)[3], 1.0, "left corner {}", corner);
+            assert!(tangent(corner)[0] > 0.99);
+        }
+        for corner in 6..12 {
+            assert_eq!(tangent(corner)[3], -1.0, "right corner {}", corner);
+            assert!(tangent(corner)[0] < -0.99);
+        }
+    }
+}
@@ -1,8 +1,15 @@
-struct CameraParams {
-    projection :                      mat4x4<f32>,
-    model :                           mat4x4<f32>,
-    view :                            mat4x4<f32>,
-    position :                        vec3<f32>,
+struct CameraUniform {
+    proj:       mat4x4<f32>,
+    model:      mat4x4<f32>,
+    view:       mat4x4<f32>,
+    position:   vec4<f32>,
+    view_proj:  mat4x4<f32>,
+    inv_proj:   mat4x4<f32>,
+    inv_view:   mat4x4<f32>,
+    viewport:   vec4<f32>,
+    near:       f32,
+    far:        f32,
+    jitter:     vec2<f32>,
 };
 
 struct VertexOutput {
@@ -12,13 +19,13 @@ struct VertexOutput {
 
 @group(0) @binding(0) var t_skybox: texture_cube<f32>;
 @group(0) @binding(1) var s_skybox: sampler;
-@group(1) @binding(0) var<uniform> camera: CameraParams;
+@group(1) @binding(0) var<uniform> camera: CameraUniform;
 
 @vertex
 fn vs_main(@location(0) position: vec3<f32>,) -> VertexOutput {
     var out: VertexOutput;
     out.world_pos = position * 10.0;
-    out.clip_pos = camera.projection * camera.view * vec4<f32>(out.world_pos, 1.0);
+    out.clip_pos = camera.proj * camera.view * vec4<f32>(out.world_pos, 1.0);
     out.clip_pos = out.clip_pos.xyww;
     return out;
 }
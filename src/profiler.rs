@@ -0,0 +1,369 @@
+use std::{collections::VecDeque, fs::File, io::{BufWriter, Write}, path::Path, sync::{atomic::{AtomicU8, Ordering}, Arc, Mutex}, time::Instant};
+
+use anyhow::Context;
+use wgpu::{Buffer, CommandEncoder, Device, Features, QuerySet, Queue, QUERY_SIZE};
+
+const FRAME_HISTORY: usize = 1000;
+const HISTOGRAM_BUCKETS: usize = 40;
+// Every scope takes two timestamps, so this allows 32 scopes per frame
+const MAX_QUERIES: u32 = 64;
+// GPU timings are read back asynchronously and arrive at most this many frames late
+const READBACK_SLOTS: usize = 3;
+
+// states of a readback slot's map_async
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+#[derive(Debug, Copy, Clone)]
+pub struct GpuScope {
+    start_query: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct GpuTiming {
+    pub label: String,
+    pub ms: f32,
+}
+
+// CPU frame times of the last FRAME_HISTORY frames, in milliseconds
+pub struct FrameStats {
+    history: VecDeque<f32>,
+}
+
+impl FrameStats {
+    fn new() -> Self {
+        Self { history: VecDeque::with_capacity(FRAME_HISTORY) }
+    }
+
+    fn push(&mut self, ms: f32) {
+        if self.history.len() == FRAME_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(ms);
+    }
+
+    pub fn latest(&self) -> f32 {
+        self.history.back().copied().unwrap_or(0.0)
+    }
+
+    pub fn average(&self) -> f32 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+        self.history.iter().sum::<f32>() / self.history.len() as f32
+    }
+
+    pub fn fps(&self) -> f32 {
+        let average = self.average();
+        if average > 0.0 { 1000.0 / average } else { 0.0 }
+    }
+
+    // Nearest rank percentile, percentile is in [0, 100]
+    pub fn percentile(&self, percentile: f32) -> f32 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+        let mut sorted: Vec<f32> = self.history.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f32).round() as usize;
+        sorted[rank]
+    }
+
+    // Frame counts per bucket, the buckets evenly split [min, max] of the history
+    pub fn histogram(&self) -> (Vec<f32>, f32, f32) {
+        let mut buckets = vec![0.0; HISTOGRAM_BUCKETS];
+        let min = self.history.iter().copied().fold(f32::MAX, f32::min);
+        let max = self.history.iter().copied().fold(0.0, f32::max);
+        if self.history.is_empty() {
+            return (buckets, 0.0, 0.0);
+        }
+        let range = (max - min).max(f32::EPSILON);
+        for ms in self.history.iter() {
+            let bucket = (((ms - min) / range) * HISTOGRAM_BUCKETS as f32) as usize;
+            buckets[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1.0;
+        }
+        (buckets, min, max)
+    }
+}
+
+struct ReadbackSlot {
+    buffer: Buffer,
+    frame: u64,
+    scopes: Vec<(String, u32)>,
+    query_count: u32,
+    // Some while the buffer is in use, holds the MAP_* state of its readback
+    mapped: Option<Arc<AtomicU8>>,
+}
+
+struct GpuTimer {
+    query_set: QuerySet,
+    slots: Vec<ReadbackSlot>,
+    current: usize,
+    // false when every readback slot is still busy, the frame goes untimed then
+    recording: bool,
+    scopes: Vec<(String, u32)>,
+    next_query: u32,
+    // nanoseconds per timestamp tick
+    period: f32,
+}
+
+impl GpuTimer {
+    fn new(device: &Device, queue: &Queue) -> Self {
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Profiler timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_QUERIES,
+        });
+        let size = (MAX_QUERIES * QUERY_SIZE) as wgpu::BufferAddress;
+        let slots = (0..READBACK_SLOTS).map(|_| ReadbackSlot {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Profiler readback buffer"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            frame: 0,
+            scopes: vec![],
+            query_count: 0,
+            mapped: None,
+        }).collect();
+        Self { query_set, slots, current: 0, recording: false, scopes: vec![], next_query: 0, period: queue.get_timestamp_period() }
+    }
+
+    fn begin_frame(&mut self) {
+        self.recording = self.slots[self.current].mapped.is_none();
+        self.scopes.clear();
+        self.next_query = 0;
+    }
+
+    fn begin_scope(&mut self, encoder: &mut CommandEncoder, label: &str) -> Option<GpuScope> {
+        if !self.recording || self.next_query + 2 > MAX_QUERIES {
+            return None;
+        }
+        let start_query = self.next_query;
+        encoder.write_timestamp(&self.query_set, start_query);
+        self.scopes.push((label.to_string(), start_query));
+        self.next_query += 2;
+        Some(GpuScope { start_query })
+    }
+
+    fn end_scope(&mut self, encoder: &mut CommandEncoder, scope: GpuScope) {
+        encoder.write_timestamp(&self.query_set, scope.start_query + 1);
+    }
+
+    fn end_frame(&mut self, device: &Device, queue: &Queue, frame: u64) {
+        if !self.recording || self.next_query == 0 {
+            return;
+        }
+        let slot = &mut self.slots[self.current];
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Profiler resolve encoder"),
+        });
+        encoder.resolve_query_set(&self.query_set, 0..self.next_query, &slot.buffer, 0);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let mapped = Arc::new(AtomicU8::new(MAP_PENDING));
+        let map_state = mapped.clone();
+        slot.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            map_state.store(if result.is_ok() { MAP_DONE } else { MAP_FAILED }, Ordering::Release);
+        });
+        slot.mapped = Some(mapped);
+        slot.frame = frame;
+        slot.query_count = self.next_query;
+        slot.scopes = std::mem::take(&mut self.scopes);
+        self.current = (self.current + 1) % self.slots.len();
+    }
+
+    // Timings of every frame whose timestamps made it back since the last call, oldest first
+    fn collect(&mut self) -> Vec<(u64, Vec<GpuTiming>)> {
+        let mut finished = vec![];
+        for slot in self.slots.iter_mut() {
+            match slot.mapped.as_ref().map(|mapped| mapped.load(Ordering::Acquire)) {
+                Some(MAP_DONE) => (),
+                Some(MAP_FAILED) => {
+                    // the frame's timings are lost, the slot is free to record again
+                    println!("Profiler readback of frame {} failed", slot.frame);
+                    slot.mapped = None;
+                    slot.scopes.clear();
+                    continue;
+                },
+                _ => continue,
+            }
+            let timestamps: Vec<u64> = {
+                let data = slot.buffer.slice(..).get_mapped_range();
+                bytemuck::cast_slice::<u8, u64>(&data[..(slot.query_count * QUERY_SIZE) as usize]).to_vec()
+            };
+            slot.buffer.unmap();
+            slot.mapped = None;
+
+            let timings = slot.scopes.drain(..).map(|(label, start_query)| {
+                let ticks = timestamps[start_query as usize + 1].saturating_sub(timestamps[start_query as usize]);
+                GpuTiming { label, ms: ticks as f32 * self.period / 1.0e6 }
+            }).collect();
+            finished.push((slot.frame, timings));
+        }
+        finished.sort_by_key(|(frame, _)| *frame);
+        finished
+    }
+}
+
+struct ProfilerState {
+    frame: u64,
+    frame_start: Option<Instant>,
+    cpu: FrameStats,
+    gpu: Option<GpuTimer>,
+    gpu_timings: Vec<GpuTiming>,
+    // what apps counted this frame and last frame's final counts
+    counters: Vec<(String, u32)>,
+    last_counters: Vec<(String, u32)>,
+    csv: Option<BufWriter<File>>,
+}
+
+// CPU frame timing plus GPU timings of the scopes apps mark with RenderTarget::begin_gpu_scope/end_gpu_scope.
+// GPU timing needs Features::TIMESTAMP_QUERY and is silently disabled without it.
+pub struct Profiler {
+    state: Mutex<ProfilerState>,
+}
+
+impl Profiler {
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let gpu = if device.features().contains(Features::TIMESTAMP_QUERY) {
+            Some(GpuTimer::new(device, queue))
+        } else {
+            None
+        };
+        Self {
+            state: Mutex::new(ProfilerState {
+                frame: 0,
+                frame_start: None,
+                cpu: FrameStats::new(),
+                gpu,
+                gpu_timings: vec![],
+                counters: vec![],
+                last_counters: vec![],
+                csv: None,
+            })
+        }
+    }
+
+    // Appends every measurement as a frame,scope,ms row
+    pub fn dump_csv(&self, path: &Path) -> anyhow::Result<()> {
+        let mut csv = BufWriter::new(File::create(path).with_context(|| format!("Failed to create {}", path.display()))?);
+        writeln!(csv, "frame,scope,ms")?;
+        self.state.lock().unwrap().csv = Some(csv);
+        Ok(())
+    }
+
+    pub fn begin_frame(&self, device: &Device) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if let Some(frame_start) = state.frame_start {
+            let ms = (now - frame_start).as_secs_f32() * 1000.0;
+            state.cpu.push(ms);
+            let frame = state.frame;
+            if let Some(csv) = state.csv.as_mut() {
+                writeln!(csv, "{},cpu_frame,{}", frame, ms).ok();
+            }
+        }
+        state.frame_start = Some(now);
+        state.frame += 1;
+        state.last_counters = std::mem::take(&mut state.counters);
+
+        device.poll(wgpu::Maintain::Poll);
+        let finished = match state.gpu.as_mut() {
+            Some(gpu) => {
+                // collect first, so the slots it frees can record this frame
+                let finished = gpu.collect();
+                gpu.begin_frame();
+                finished
+            },
+            None => vec![],
+        };
+        for (frame, timings) in finished {
+            if let Some(csv) = state.csv.as_mut() {
+                for timing in timings.iter() {
+                    writeln!(csv, "{},{},{}", frame, timing.label, timing.ms).ok();
+                }
+            }
+            state.gpu_timings = timings;
+        }
+    }
+
+    pub fn end_frame(&self, device: &Device, queue: &Queue) {
+        let mut state = self.state.lock().unwrap();
+        let frame = state.frame;
+        if let Some(gpu) = state.gpu.as_mut() {
+            gpu.end_frame(device, queue, frame);
+        }
+    }
+
+    pub fn begin_gpu_scope(&self, encoder: &mut CommandEncoder, label: &str) -> Option<GpuScope> {
+        self.state.lock().unwrap().gpu.as_mut().and_then(|gpu| gpu.begin_scope(encoder, label))
+    }
+
+    pub fn end_gpu_scope(&self, encoder: &mut CommandEncoder, scope: GpuScope) {
+        if let Some(gpu) = self.state.lock().unwrap().gpu.as_mut() {
+            gpu.end_scope(encoder, scope);
+        }
+    }
+
+    // Adds to a per frame count like drawn primitives, shown next to the timings
+    pub fn count(&self, label: &str, value: u32) {
+        let mut state = self.state.lock().unwrap();
+        match state.counters.iter_mut().find(|(counter, _)| counter == label) {
+            Some((_, count)) => *count += value,
+            None => state.counters.push((label.to_string(), value)),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut summary = format!("{:.1} fps, frame {:.2} ms avg, {:.2} ms p95, {:.2} ms p99",
+                                  state.cpu.fps(), state.cpu.average(), state.cpu.percentile(95.0), state.cpu.percentile(99.0));
+        for timing in state.gpu_timings.iter() {
+            summary += &format!(", {} {:.2} ms", timing.label, timing.ms);
+        }
+        for (label, count) in state.last_counters.iter() {
+            summary += &format!(", {} {}", label, count);
+        }
+        summary
+    }
+
+    pub fn draw_imgui(&self, ui: &imgui::Ui) {
+        let state = self.state.lock().unwrap();
+        let cpu = &state.cpu;
+        ui.window("Frame timings")
+            .size([320.0, 300.0], imgui::Condition::FirstUseEver)
+            .position([10.0, 80.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.text(format!("{:.1} fps, {:.2} ms", cpu.fps(), cpu.latest()));
+                ui.text(format!("avg {:.2}  p50 {:.2}  p95 {:.2}  p99 {:.2}  max {:.2}",
+                                cpu.average(), cpu.percentile(50.0), cpu.percentile(95.0), cpu.percentile(99.0), cpu.percentile(100.0)));
+                let history: Vec<f32> = cpu.history.iter().copied().collect();
+                ui.plot_lines("Frame ms", &history)
+                    .scale_min(0.0)
+                    .graph_size([0.0, 60.0])
+                    .build();
+                let (histogram, min, max) = cpu.histogram();
+                ui.plot_histogram("Distribution", &histogram)
+                    .overlay_text(format!("{:.2} - {:.2} ms", min, max))
+                    .scale_min(0.0)
+                    .graph_size([0.0, 60.0])
+                    .build();
+                ui.separator();
+                if state.gpu.is_none() {
+                    ui.text("GPU timings need TIMESTAMP_QUERY");
+                }
+                for timing in state.gpu_timings.iter() {
+                    ui.text(format!("{}: {:.3} ms", timing.label, timing.ms));
+                }
+                if !state.last_counters.is_empty() {
+                    ui.separator();
+                }
+                for (label, count) in state.last_counters.iter() {
+                    ui.text(format!("{}: {}", label, count));
+                }
+            });
+    }
+}
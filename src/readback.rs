@@ -0,0 +1,81 @@
+use std::{iter, num::NonZeroU32, sync::mpsc};
+
+use anyhow::{bail, Context};
+use image::RgbaImage;
+use wgpu::{Device, Queue, Texture, TextureFormat, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+// Copies the first mip of a 2D color texture into a mappable buffer and returns it as a tightly packed RGBA image.
+// The texture has to be created with TextureUsages::COPY_SRC.
+pub fn read_texture(device: &Device, queue: &Queue, texture: &Texture, format: TextureFormat, width: u32, height: u32) -> anyhow::Result<RgbaImage> {
+    let swap_red_blue = match format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+        _ => bail!("Readback of {:?} textures is not supported", format),
+    };
+
+    let unpadded_bytes_per_row = 4 * width;
+    let padded_bytes_per_row = padded_bytes_per_row(width);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                rows_per_image: NonZeroU32::new(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(iter::once(encoder.finish()));
+
+    let buffer_slice = buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).ok();
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv().context("Readback buffer mapping was dropped")??;
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+        let data = buffer_slice.get_mapped_range();
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    buffer.unmap();
+
+    if swap_red_blue {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    RgbaImage::from_raw(width, height, pixels).context("Readback size mismatch")
+}
+
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded_bytes_per_row = 4 * width;
+    let padding = (COPY_BYTES_PER_ROW_ALIGNMENT - unpadded_bytes_per_row % COPY_BYTES_PER_ROW_ALIGNMENT) % COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded_bytes_per_row + padding
+}
@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use wgpu::{CommandEncoder, RenderPass, Surface, SurfaceConfiguration, SurfaceError, SurfaceTexture, Texture, TextureFormat, TextureView};
+
+use crate::profiler::{GpuScope, Profiler};
+
+#[derive(Debug, Copy, Clone)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// What an App draws into for a single frame. It is either a swapchain image that
+// has to be presented afterwards or any texture owned by the caller
+// (offscreen rendering, capture, editor panels).
+pub struct RenderTarget {
+    pub view: TextureView,
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    // Restricts drawing to a part of the target. Note that clear load ops still affect the whole attachment.
+    pub viewport: Option<Viewport>,
+    // Set by runners that measure frame timings, apps use it to time their passes and draw the stats
+    pub profiler: Option<Arc<Profiler>>,
+    // Set when the frame gets written out, window-only overlays like the example menu stay off it then
+    pub captured: bool,
+    surface_texture: Option<SurfaceTexture>,
+}
+
+impl RenderTarget {
+    pub fn from_surface(surface: &Surface, config: &SurfaceConfiguration) -> Result<Self, SurfaceError> {
+        let surface_texture = surface.get_current_texture()?;
+        let view = surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        Ok(Self {
+            view,
+            format: config.format,
+            width: config.width,
+            height: config.height,
+            viewport: None,
+            profiler: None,
+            captured: false,
+            surface_texture: Some(surface_texture),
+        })
+    }
+
+    pub fn from_texture(texture: &Texture, format: TextureFormat, width: u32, height: u32) -> Self {
+        Self {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            format,
+            width,
+            height,
+            viewport: None,
+            profiler: None,
+            captured: false,
+            surface_texture: None,
+        }
+    }
+
+    // For embedders drawing into a part of an editor viewport, none of the runners here does
+    #[allow(dead_code)]
+    pub fn with_viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = Some(viewport);
+        self
+    }
+
+    pub fn with_profiler(mut self, profiler: Arc<Profiler>) -> Self {
+        self.profiler = Some(profiler);
+        self
+    }
+
+    pub fn captured(mut self) -> Self {
+        self.captured = true;
+        self
+    }
+
+    // Both are no-ops without a profiler or without timestamp query support
+    pub fn begin_gpu_scope(&self, encoder: &mut CommandEncoder, label: &str) -> Option<GpuScope> {
+        self.profiler.as_ref().and_then(|profiler| profiler.begin_gpu_scope(encoder, label))
+    }
+
+    pub fn end_gpu_scope(&self, encoder: &mut CommandEncoder, scope: Option<GpuScope>) {
+        if let (Some(profiler), Some(scope)) = (self.profiler.as_ref(), scope) {
+            profiler.end_gpu_scope(encoder, scope);
+        }
+    }
+
+    // No-op without a profiler
+    pub fn count(&self, label: &str, value: u32) {
+        if let Some(profiler) = self.profiler.as_ref() {
+            profiler.count(label, value);
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        match self.viewport {
+            Some(viewport) => [viewport.width, viewport.height],
+            None => [self.width, self.height],
+        }
+    }
+
+    pub fn apply_viewport(&self, render_pass: &mut RenderPass) {
+        if let Some(viewport) = self.viewport {
+            render_pass.set_viewport(viewport.x as f32, viewport.y as f32, viewport.width as f32, viewport.height as f32, 0.0, 1.0);
+            render_pass.set_scissor_rect(viewport.x, viewport.y, viewport.width, viewport.height);
+        }
+    }
+
+    // Presents the frame if the target came from a surface, otherwise does nothing.
+    pub fn present(self) {
+        if let Some(surface_texture) = self.surface_texture {
+            surface_texture.present();
+        }
+    }
+}
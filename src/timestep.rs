@@ -0,0 +1,45 @@
+use crate::{app::{App, AppVariant}, assets_helper::ResourceManager};
+
+// Guards against the spiral of death: when a frame took so long that more steps than this are due,
+// the simulation drops the remaining time and runs slower than real time instead
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+// Turns frame deltas into App::update calls, either one variable step per frame
+// or as many fixed steps as fit into the accumulated time.
+pub enum Timestep {
+    Variable,
+    Fixed { step: f32, accumulator: f32 },
+}
+
+impl Timestep {
+    pub fn from_variant(app_variant: &AppVariant) -> Self {
+        match app_variant.fixed_timestep {
+            Some(rate) if rate > 0.0 => Timestep::Fixed { step: 1.0 / rate, accumulator: 0.0 },
+            _ => Timestep::Variable,
+        }
+    }
+
+    // Runs the simulation for a frame and returns the interpolation alpha for App::render
+    pub fn advance<T: ResourceManager, A: App<T> + ?Sized>(&mut self, app_instance: &mut A, delta: f32) -> f32 {
+        match self {
+            Timestep::Variable => {
+                app_instance.update(delta);
+                1.0
+            },
+            Timestep::Fixed { step, accumulator } => {
+                *accumulator += delta;
+                let mut steps = 0;
+                while *accumulator >= *step {
+                    if steps == MAX_STEPS_PER_FRAME {
+                        *accumulator = 0.0;
+                        break;
+                    }
+                    app_instance.update(*step);
+                    *accumulator -= *step;
+                    steps += 1;
+                }
+                *accumulator / *step
+            },
+        }
+    }
+}
@@ -1,9 +1,9 @@
-use std::{collections::VecDeque, path::Path};
+use std::{path::Path, sync::Arc};
 
-use wgpu::{InstanceDescriptor, Backends, RequestAdapterOptions, Limits, DeviceDescriptor, TextureUsages, SurfaceConfiguration};
-use winit::{event_loop::{EventLoop, ControlFlow}, event::{Event, WindowEvent, KeyboardInput, ElementState, VirtualKeyCode}, window::Icon};
+use wgpu::{InstanceDescriptor, Backends, RequestAdapterOptions, Limits, DeviceDescriptor, TextureUsages, SurfaceConfiguration, Surface, Device, Features};
+use winit::{dpi::PhysicalSize, event_loop::{EventLoop, ControlFlow}, event::{Event, WindowEvent, KeyboardInput, ElementState, VirtualKeyCode}, window::Icon};
 
-use crate::{app::{App, AppVariant}, assets_helper::DesktopResourceManager, input_event::InputEvent};
+use crate::{app::{App, AppVariant}, assets_helper::DesktopResourceManager, camera_path::CameraPathDriver, capture::FrameCapture, input_event::WinitInputTranslator, input_state::{ActionMap, InputState}, profiler::Profiler, render_target::RenderTarget, timestep::Timestep};
 
 pub async fn run<T: App<DesktopResourceManager> + 'static>(title: &str, app_variant: AppVariant) {
     env_logger::init();
@@ -39,7 +39,9 @@ pub async fn run<T: App<DesktopResourceManager> + 'static>(title: &str, app_vari
         .request_device(
             &DeviceDescriptor {
                 label: None,
-                features: T::get_extra_device_features(app_variant),
+                // timestamps are only needed by the profiler, so they're requested whenever available
+                features: T::get_extra_device_features(&app_variant)
+                    | (adapter.features() & (T::get_optional_device_features(&app_variant) | Features::TIMESTAMP_QUERY)),
                 limits: Limits::default()
             },
             None
@@ -56,43 +58,42 @@ pub async fn run<T: App<DesktopResourceManager> + 'static>(title: &str, app_vari
     };
     surface.configure(&device, &surface_config);
 
-    let mut app_instance = T::new(&surface_config, &device, queue, app_variant.shader_type, &DesktopResourceManager{});
+    let queue = Arc::new(queue);
+    let mut app_instance = T::from_variant(&surface_config, &device, queue.clone(), &app_variant, &DesktopResourceManager{});
+    let mut capture = FrameCapture::new(app_variant.output_dir.clone(), app_variant.capture_fps);
+    let profiler = Arc::new(Profiler::new(&device, &queue));
+    if let Some(path) = &app_variant.profile_csv {
+        if let Err(error) = profiler.dump_csv(path) {
+            println!("Frame timings won't be dumped - {:?}", error);
+        }
+    }
 
+    let mut timestep = Timestep::from_variant(&app_variant);
     let mut moment = std::time::Instant::now();
-    let mut fps_data = VecDeque::new();
-    let mut latest_fps_print = std::time::Instant::now();
-    let mut input_event = InputEvent::default();
+    let mut latest_stats_print = std::time::Instant::now();
+    let mut input_translator = WinitInputTranslator::default();
+    let actions = match &app_variant.bindings {
+        Some(path) => ActionMap::load(path).unwrap_or_else(|error| {
+            println!("Falling back to the default bindings - {:?}", error);
+            ActionMap::default()
+        }),
+        None => ActionMap::default(),
+    };
+    let mut input_state = InputState::new(actions);
+    let mut camera_path = CameraPathDriver::from_variant(&app_variant);
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
 
-        let duration = moment.elapsed();
-        let delta = duration.as_secs_f32() + (duration.subsec_nanos() as f32 * 1.0e-9);
-        if fps_data.len() > 1000 {
-            fps_data.pop_front();
-        }
-        fps_data.push_back(delta);
-        moment = std::time::Instant::now();
-        
-        // TODO this isn't fps. redo
-        if latest_fps_print.elapsed().as_secs_f32() > 1.0 {
-            println!(
-                "Avg fps: {}",
-                1.0 / (fps_data.iter().sum::<f32>() / fps_data.len() as f32)
-            );
-            latest_fps_print = std::time::Instant::now();
-        }
-
-        app_instance.tick(delta);
-        
         match event {
             Event::MainEventsCleared => window.request_redraw(),
             Event::WindowEvent {
                 ref event,
                 window_id,
             } if window_id == window.id() => {
-                let new_event = InputEvent::from_winit_event(event);
-                //if !app_instance.process_input(&InputEvent::diff(&input_event, &new_event)) {
-                if !app_instance.process_input(&new_event) {
+                let input_event = input_translator.translate(event);
+                // the state sees every event so that a swallowed release doesn't leave a key stuck
+                input_state.handle(&input_event);
+                if !app_instance.process_input(&input_event) {
                     match event {
                         WindowEvent::CloseRequested | WindowEvent::KeyboardInput {
                             input:
@@ -103,25 +104,76 @@ pub async fn run<T: App<DesktopResourceManager> + 'static>(title: &str, app_vari
                                 },
                             ..
                         } => *control_flow = ControlFlow::Exit,
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(key @ (VirtualKeyCode::F11 | VirtualKeyCode::F12)),
+                                    ..
+                                },
+                            ..
+                        } => match key {
+                            VirtualKeyCode::F12 => capture.request_screenshot(),
+                            _ => capture.toggle_sequence(),
+                        },
                         WindowEvent::Resized(physical_size) => {
-                            surface_config.width = physical_size.width;
-                            surface_config.height = physical_size.height;
-                            surface.configure(&device, &surface_config);
-
-                            app_instance.resize(&surface_config, &device);
+                            resize_surface(&surface, &device, &mut surface_config, *physical_size, &mut app_instance);
                         }
-                        WindowEvent::ScaleFactorChanged { .. } => {
-                            app_instance.resize(&surface_config, &device);
+                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                            // Wayland and X11 with per-monitor scaling report the new size only here
+                            resize_surface(&surface, &device, &mut surface_config, **new_inner_size, &mut app_instance);
                         },
                         _ => {}
                     }
                 }
-                input_event = new_event;
             }
-            Event::RedrawRequested(window_id) if window_id == window.id() => {
-                match app_instance.render(&surface, &device) {
-                    Ok(_) => {}
-                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => app_instance.resize(&surface_config, &device),
+            // minimized windows (X11 reports a 0x0 size) have nothing to draw into
+            Event::RedrawRequested(window_id) if window_id == window.id() && surface_config.width > 0 && surface_config.height > 0 => {
+                let delta = moment.elapsed().as_secs_f32();
+                moment = std::time::Instant::now();
+                profiler.begin_frame(&device);
+                if latest_stats_print.elapsed().as_secs_f32() > 1.0 {
+                    println!("{}", profiler.summary());
+                    latest_stats_print = std::time::Instant::now();
+                }
+
+                match RenderTarget::from_surface(&surface, &surface_config) {
+                    Ok(surface_target) => {
+                        let surface_target = surface_target.with_profiler(profiler.clone());
+                        // captured frames are drawn offscreen and blitted to the surface, surface textures can't be copied from everywhere
+                        let capture_target = capture.wants_frame()
+                            .then(|| capture.target(&device, surface_config.format, surface_config.width, surface_config.height).with_profiler(profiler.clone()));
+                        let target = capture_target.as_ref().unwrap_or(&surface_target);
+                        // recorded sequences advance by a fixed step, however long the frame really took
+                        let delta = capture.sequence_delta().unwrap_or(delta);
+                        app_instance.process_input_state(&input_state);
+                        if input_state.was_released("record_keyframe") {
+                            camera_path.record_keyframe(&mut app_instance);
+                        }
+                        input_state.end_frame();
+                        camera_path.advance(&mut app_instance, delta);
+                        app_instance.tick(delta);
+                        let alpha = timestep.advance(&mut app_instance, delta);
+                        // the whole frame is bracketed with timestamps in encoders of its own, apps may add finer scopes
+                        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Frame begin encoder") });
+                        let frame_scope = target.begin_gpu_scope(&mut encoder, "GPU frame");
+                        queue.submit(std::iter::once(encoder.finish()));
+                        app_instance.render(target, &device, alpha);
+                        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Frame end encoder") });
+                        target.end_gpu_scope(&mut encoder, frame_scope);
+                        queue.submit(std::iter::once(encoder.finish()));
+                        if capture_target.is_some() {
+                            if let Err(error) = capture.capture(&device, &queue, &surface_target.view) {
+                                println!("Frame capture failed - {:?}", error);
+                            }
+                        }
+                        surface_target.present();
+                        profiler.end_frame(&device, &queue);
+                    }
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        surface.configure(&device, &surface_config);
+                        app_instance.resize(&surface_config, &device);
+                    }
                     Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                     Err(wgpu::SurfaceError::Timeout) => log::warn!("Surface timeout"),
                 };
@@ -129,4 +181,15 @@ pub async fn run<T: App<DesktopResourceManager> + 'static>(title: &str, app_vari
             _ => {}
         }
     });
-}
\ No newline at end of file
+}
+
+fn resize_surface<T: App<DesktopResourceManager>>(surface: &Surface, device: &Device, surface_config: &mut SurfaceConfiguration, size: PhysicalSize<u32>, app_instance: &mut T) {
+    surface_config.width = size.width;
+    surface_config.height = size.height;
+    // configuring a surface with a zero extent is a validation error, wait for the window to be restored
+    if size.width == 0 || size.height == 0 {
+        return;
+    }
+    surface.configure(device, surface_config);
+    app_instance.resize(surface_config, device);
+}